@@ -0,0 +1,532 @@
+// The allocation shared by Ark and Rk, generic over the type of the reference counters.
+//
+// Everything here deals with the layout of the allocation, with initializing it, and with the
+// primitive operations on its counts (and their orderings); what the counts mean, and when they
+// are updated, is up to each pointer type.
+
+use allocator_api2::alloc::Allocator;
+use std::{
+    alloc::{alloc, dealloc, handle_alloc_error, Layout},
+    cell::Cell,
+    mem::{self, MaybeUninit},
+    ptr::{self, NonNull},
+};
+
+use crate::sync::{hint, AtomicUsize, Ordering};
+
+/// The value of a weak_count that is temporarily locked by Ark::is_unique(), to prevent new
+/// ArkWeaks from being created while it checks strong_count.
+pub(crate) const WEAK_LOCKED: usize = usize::MAX;
+
+// Like in std::sync::Arc, counts are allowed to slightly exceed this (by the number of threads
+// concurrently incrementing them) before we notice and abort, so it must leave plenty of room
+// before usize::MAX (and WEAK_LOCKED).
+pub(crate) const MAX_REFCOUNT: usize = isize::MAX as usize;
+
+// A count can only get this large if references are being leaked (e.g. with mem::forget()), and
+// letting it wrap around would result in a use-after-free.  Panicking is not enough, since the
+// panic could be caught and the leaking resumed; abort instead.
+#[cold]
+#[inline(never)]
+fn refcount_overflow() -> ! {
+    std::process::abort();
+}
+
+/// The type of the reference counts of a [`Shared`](crate::Shared) pointer.
+///
+/// This is implemented for `AtomicUsize` (used by [`Ark`](crate::Ark)) and for `Cell<usize>`
+/// (used by `Rk`), and cannot be implemented outside of this crate.  The orderings are those that
+/// Ark needs; see the implementation for `AtomicUsize`.
+pub trait Counter: private::Sealed {
+    fn new(count: usize) -> Self;
+
+    /// Gets the count, synchronizing with the last decrement.
+    fn get(&self) -> usize;
+
+    /// Sets the count, publishing the writes made before to whoever increments it next.
+    fn set(&self, count: usize);
+
+    /// Increments the count, aborting if it overflows.
+    fn increment(&self);
+
+    /// Increments the count, unless it is zero (in which case `false` is returned).
+    fn increment_unless_zero(&self) -> bool;
+
+    /// Increments the count, waiting for it to be unlocked first if it is locked by `is_unique()`.
+    fn increment_unless_locked(&self);
+
+    /// Decrements the count, returning `true` if it reached zero.
+    fn decrement(&self) -> bool;
+
+    /// Sets the count to `new` if it is `current`, returning whether it was.
+    fn compare_and_set(&self, current: usize, new: usize) -> bool;
+}
+
+mod private {
+    pub trait Sealed {}
+
+    impl Sealed for crate::sync::AtomicUsize {}
+    impl Sealed for std::cell::Cell<usize> {}
+}
+
+impl Counter for AtomicUsize {
+    fn new(count: usize) -> Self {
+        AtomicUsize::new(count)
+    }
+
+    fn get(&self) -> usize {
+        // Ordering: Acquire synchronizes with the Release half of the fetch_sub() in decrement(),
+        // so that the accesses made by the references that were released happen before ours.
+        self.load(Ordering::Acquire)
+    }
+
+    fn set(&self, count: usize) {
+        // Ordering: Release synchronizes with the Acquire in the other operations.
+        self.store(count, Ordering::Release);
+    }
+
+    fn increment(&self) {
+        // Ordering: there are no accesses to synchronize, and the allocation is not going
+        // anywhere because incrementing requires an existing reference.
+        let old = self.fetch_add(1, Ordering::Relaxed);
+
+        // Checking the old value is enough: to reach usize::MAX from here, isize::MAX threads
+        // would have to be concurrently between the increment above and this check.
+        if old > MAX_REFCOUNT {
+            refcount_overflow();
+        }
+    }
+
+    fn increment_unless_zero(&self) -> bool {
+        // Ordering: the count must never be incremented from zero, so we cannot simply use
+        // fetch_add() like in increment().  Acquire on success is not strictly necessary (the
+        // data is only accessed through the resulting reference, which got to exist because
+        // another one still held it), but it is cheap and keeps this in line with std::sync::Weak.
+        let mut n = self.load(Ordering::Relaxed);
+        loop {
+            if n == 0 {
+                return false;
+            }
+
+            if n > MAX_REFCOUNT {
+                refcount_overflow();
+            }
+
+            match self.compare_exchange_weak(n, n + 1, Ordering::Acquire, Ordering::Relaxed) {
+                Ok(_) => return true,
+                Err(old) => n = old,
+            }
+        }
+    }
+
+    fn increment_unless_locked(&self) {
+        // Ordering: while the count is locked we must wait (it will be unlocked soon); otherwise,
+        // there are no accesses to synchronize.  Acquire on success synchronizes with the
+        // unlocking set().
+        let mut n = self.load(Ordering::Relaxed);
+        loop {
+            if n == WEAK_LOCKED {
+                hint::spin_loop();
+                n = self.load(Ordering::Relaxed);
+                continue;
+            }
+
+            if n > MAX_REFCOUNT {
+                refcount_overflow();
+            }
+
+            match self.compare_exchange_weak(n, n + 1, Ordering::Acquire, Ordering::Relaxed) {
+                Ok(_) => return,
+                Err(old) => n = old,
+            }
+        }
+    }
+
+    fn decrement(&self) -> bool {
+        // Ordering: Release makes our accesses happen before whatever the last reference does
+        // once the count reaches zero (like dropping the data or freeing the allocation), and
+        // Acquire makes the accesses of all other references happen before that, if we are it.
+        self.fetch_sub(1, Ordering::AcqRel) == 1
+    }
+
+    fn compare_and_set(&self, current: usize, new: usize) -> bool {
+        // Ordering: Acquire on success synchronizes with the Release half of the fetch_sub() in
+        // decrement(), so that the accesses made by the released references happen before ours.
+        self.compare_exchange(current, new, Ordering::Acquire, Ordering::Relaxed)
+            .is_ok()
+    }
+}
+
+// Rk is not Send nor Sync, so there are no orderings to care about; the counts are checked before
+// being incremented, since no one else can increment them concurrently.
+impl Counter for Cell<usize> {
+    fn new(count: usize) -> Self {
+        Cell::new(count)
+    }
+
+    fn get(&self) -> usize {
+        Cell::get(self)
+    }
+
+    fn set(&self, count: usize) {
+        Cell::set(self, count);
+    }
+
+    fn increment(&self) {
+        let n = self.get();
+        if n > MAX_REFCOUNT {
+            refcount_overflow();
+        }
+        self.set(n + 1);
+    }
+
+    fn increment_unless_zero(&self) -> bool {
+        if self.get() == 0 {
+            return false;
+        }
+        self.increment();
+        true
+    }
+
+    fn increment_unless_locked(&self) {
+        // A lock is only ever held for the duration of a single Ark method, so with no other
+        // threads around it cannot be observed here.
+        debug_assert_ne!(self.get(), WEAK_LOCKED);
+        self.increment();
+    }
+
+    fn decrement(&self) -> bool {
+        self.set(self.get() - 1);
+        self.get() == 0
+    }
+
+    fn compare_and_set(&self, current: usize, new: usize) -> bool {
+        if self.get() != current {
+            return false;
+        }
+        self.set(new);
+        true
+    }
+}
+
+#[repr(C)]
+pub(crate) struct Inner<C, T: ?Sized> {
+    pub(crate) strong_count: C,
+
+    // The number of weak references, plus one if there are any strong references: all strong
+    // references collectively hold a single weak reference, which keeps the allocation alive
+    // while the data is being dropped.
+    pub(crate) weak_count: C,
+
+    pub(crate) data: T,
+}
+
+// Both counters can be reinterpreted as the other (see Rk::into_ark()): AtomicUsize is documented
+// to have the same in-memory representation as usize, and Cell<usize> is #[repr(transparent)].
+#[cfg(not(feature = "loom"))]
+const _: () = {
+    assert!(mem::size_of::<AtomicUsize>() == mem::size_of::<Cell<usize>>());
+    assert!(mem::align_of::<AtomicUsize>() == mem::align_of::<Cell<usize>>());
+};
+
+impl<C: Counter, T> Inner<C, T> {
+    /// Allocates a new `Inner` with both counts set to one.
+    pub(crate) fn new(data: T) -> NonNull<Inner<C, T>> {
+        let inner = Box::new(Inner {
+            strong_count: C::new(1),
+            weak_count: C::new(1),
+            data,
+        });
+
+        // Safety: a pointer created with Box::into_raw() cannot be null.
+        unsafe { NonNull::new_unchecked(Box::into_raw(inner)) }
+    }
+}
+
+/// Allocates a new `Inner` in `alloc`, with both counts set to one.
+pub(crate) fn new_in<C: Counter, T, A: Allocator>(data: T, alloc: &A) -> NonNull<Inner<C, T>> {
+    let layout = Layout::new::<Inner<C, T>>();
+
+    let inner = match alloc.allocate(layout) {
+        Ok(mem) => mem.cast::<Inner<C, T>>(),
+        Err(_) => handle_alloc_error(layout),
+    };
+
+    // Safety: the allocation was just made with the layout of Inner<C, T>.
+    unsafe {
+        ptr::write(
+            inner.as_ptr(),
+            Inner {
+                strong_count: C::new(1),
+                weak_count: C::new(1),
+                data,
+            },
+        );
+    }
+
+    inner
+}
+
+// Because Inner is #[repr(C)], its layout is exactly the layout of the counters followed by the
+// (suitably aligned) data; this matches the layout that Box would have used, which is important
+// since the allocation is freed with Layout::for_value().
+fn inner_layout<C>(value_layout: Layout) -> Layout {
+    let (layout, _) = Layout::new::<Inner<C, ()>>()
+        .extend(value_layout)
+        .expect("allocation size overflow");
+    layout.pad_to_align()
+}
+
+/// # Safety
+///
+/// `value_layout` must be the layout of the data, and `mem_to_inner` must return a pointer to
+/// `mem` that has the correct metadata for the data that the caller will then initialize.
+unsafe fn allocate_for_layout<C: Counter, T: ?Sized>(
+    value_layout: Layout,
+    mem_to_inner: impl FnOnce(*mut u8) -> *mut Inner<C, T>,
+) -> *mut Inner<C, T> {
+    let layout = inner_layout::<C>(value_layout);
+
+    // Safety: the layout is never zero sized, because of the counters.
+    let mem = alloc(layout);
+    if mem.is_null() {
+        handle_alloc_error(layout);
+    }
+
+    let inner = mem_to_inner(mem);
+
+    ptr::write(ptr::addr_of_mut!((*inner).strong_count), C::new(1));
+    ptr::write(ptr::addr_of_mut!((*inner).weak_count), C::new(1));
+
+    inner
+}
+
+/// # Safety
+///
+/// The caller must initialize all `len` elements of the data before using the returned pointer.
+unsafe fn allocate_for_slice<C: Counter, T>(len: usize) -> *mut Inner<C, [T]> {
+    let value_layout = Layout::array::<T>(len).expect("allocation size overflow");
+
+    // Casting a slice pointer into a pointer to Inner<C, [T]> keeps the length metadata.
+    allocate_for_layout(value_layout, |mem| {
+        ptr::slice_from_raw_parts_mut(mem as *mut T, len) as *mut Inner<C, [T]>
+    })
+}
+
+/// Allocates a new `Inner` with uninitialized data, without going through the stack.
+pub(crate) fn new_uninit<C: Counter, T>() -> NonNull<Inner<C, MaybeUninit<T>>> {
+    // Safety: MaybeUninit<T> needs no initialization, and its layout is the layout of T.
+    unsafe {
+        let inner = allocate_for_layout(Layout::new::<T>(), |mem| {
+            mem as *mut Inner<C, MaybeUninit<T>>
+        });
+        NonNull::new_unchecked(inner)
+    }
+}
+
+/// Allocates a new `Inner` for a slice of uninitialized elements.
+pub(crate) fn new_uninit_slice<C: Counter, T>(len: usize) -> NonNull<Inner<C, [MaybeUninit<T>]>> {
+    // Safety: MaybeUninit<T> needs no initialization.
+    unsafe { NonNull::new_unchecked(allocate_for_slice::<C, MaybeUninit<T>>(len)) }
+}
+
+/// Allocates a new `Inner` for a slice, moving the elements out of `v`.
+pub(crate) fn from_vec<C: Counter, T>(mut v: Vec<T>) -> NonNull<Inner<C, [T]>> {
+    let len = v.len();
+
+    // Safety: all elements are moved (bitwise) into the new allocation, and then forgotten by the
+    // Vec, so that only its buffer is freed.
+    unsafe {
+        let inner = allocate_for_slice::<C, T>(len);
+        let data = ptr::addr_of_mut!((*inner).data) as *mut T;
+        ptr::copy_nonoverlapping(v.as_ptr(), data, len);
+        v.set_len(0);
+        NonNull::new_unchecked(inner)
+    }
+}
+
+/// Allocates a new `Inner` for a slice, cloning the elements of `v`.
+pub(crate) fn from_slice<C: Counter, T: Clone>(v: &[T]) -> NonNull<Inner<C, [T]>> {
+    // Drops the already cloned elements and frees the allocation if T::clone() panics.
+    struct Guard<T> {
+        mem: *mut u8,
+        layout: Layout,
+        data: *mut T,
+        initialized: usize,
+    }
+
+    impl<T> Drop for Guard<T> {
+        fn drop(&mut self) {
+            // Safety: only the first `initialized` elements have been written, and the allocation
+            // has not been handed to any pointer type yet.
+            unsafe {
+                ptr::drop_in_place(ptr::slice_from_raw_parts_mut(self.data, self.initialized));
+                dealloc(self.mem, self.layout);
+            }
+        }
+    }
+
+    // Safety: each element is initialized exactly once before the pointer is returned, and on
+    // panic the guard cleans up the partially initialized allocation.
+    unsafe {
+        let inner = allocate_for_slice::<C, T>(v.len());
+        let data = ptr::addr_of_mut!((*inner).data) as *mut T;
+
+        let mut guard = Guard {
+            mem: inner as *mut u8,
+            layout: inner_layout::<C>(Layout::for_value(v)),
+            data,
+            initialized: 0,
+        };
+
+        for (i, item) in v.iter().enumerate() {
+            ptr::write(data.add(i), item.clone());
+            guard.initialized += 1;
+        }
+
+        mem::forget(guard);
+        NonNull::new_unchecked(inner)
+    }
+}
+
+/// Converts an `Inner` for a byte slice into one for a `str`.
+///
+/// # Safety
+///
+/// The bytes must be valid UTF-8.
+pub(crate) unsafe fn bytes_to_str<C>(bytes: NonNull<Inner<C, [u8]>>) -> NonNull<Inner<C, str>> {
+    // str has the same layout (and metadata) as [u8].
+    NonNull::new_unchecked(bytes.as_ptr() as *mut Inner<C, str>)
+}
+
+/// Allocates a new `Inner`, moving the data out of `b`.
+pub(crate) fn from_box<C: Counter, T: ?Sized>(b: Box<T>) -> NonNull<Inner<C, T>> {
+    let value_layout = Layout::for_value(&*b);
+    let b = Box::into_raw(b);
+
+    // Safety: the data is moved (bitwise) out of the box, and then the box is freed without
+    // dropping it.  The pointer to the new allocation is built from `mem`, and only takes the
+    // metadata (slice length or vtable) of the data from the box pointer.
+    unsafe {
+        let inner =
+            allocate_for_layout(value_layout, |mem| set_data_ptr(b as *mut Inner<C, T>, mem));
+        let data = ptr::addr_of_mut!((*inner).data) as *mut u8;
+        ptr::copy_nonoverlapping(b as *const u8, data, value_layout.size());
+
+        if value_layout.size() != 0 {
+            dealloc(b as *mut u8, value_layout);
+        }
+
+        NonNull::new_unchecked(inner)
+    }
+}
+
+/// Returns a pointer to the data, which is allowed to access the whole allocation.
+pub(crate) fn data_ptr<C, T: ?Sized>(inner: NonNull<Inner<C, T>>) -> *mut T {
+    // Safety: no reference is created, only a place projection from a valid pointer.
+    unsafe { ptr::addr_of_mut!((*inner.as_ptr()).data) }
+}
+
+/// Recovers the pointer to an `Inner` from a pointer returned by [`data_ptr`].
+///
+/// # Safety
+///
+/// `ptr` must have been returned by `data_ptr()`, and the data must still be alive.
+pub(crate) unsafe fn from_data_ptr<C, T: ?Sized>(ptr: *const T) -> NonNull<Inner<C, T>> {
+    // The data is at a fixed offset after the counters, which depends on its alignment; the
+    // reference to the data is fine, since it must still be alive.
+    let (_, offset) = Layout::new::<Inner<C, ()>>()
+        .extend(Layout::for_value(&*ptr))
+        .expect("valid allocations never overflow");
+
+    // Casting to a pointer to Inner<C, T> and then fixing its address (but not its metadata) gets
+    // us back a pointer to the whole allocation, as originally returned by Box (or
+    // allocate_for_layout()).
+    let inner = set_data_ptr(ptr as *mut Inner<C, T>, (ptr as *mut u8).sub(offset));
+
+    NonNull::new_unchecked(inner)
+}
+
+/// The layout of an allocation, as needed to free it after its data has been dropped.
+///
+/// With the `nightly` feature, the layout is computed from the pointer when it is needed, and this
+/// is zero sized.  Stable Rust can only compute the layout of an unsized value through a
+/// reference, which must not be created once the data has been dropped; so the layout is computed
+/// while the data is still alive, and carried along by each weak reference until it frees the
+/// allocation.
+#[derive(Clone, Copy)]
+pub(crate) struct AllocLayout {
+    #[cfg(not(feature = "nightly"))]
+    layout: Layout,
+}
+
+impl AllocLayout {
+    /// # Safety
+    ///
+    /// `inner` must point to a live allocation whose data is still initialized.
+    #[cfg_attr(feature = "nightly", allow(unused_variables))]
+    pub(crate) unsafe fn of<C, T: ?Sized>(inner: NonNull<Inner<C, T>>) -> AllocLayout {
+        AllocLayout {
+            #[cfg(not(feature = "nightly"))]
+            layout: Layout::for_value(inner.as_ref()),
+        }
+    }
+
+    /// # Safety
+    ///
+    /// `self` must have been computed for `inner`, which must not have been freed yet.
+    #[cfg(feature = "nightly")]
+    unsafe fn get<C, T: ?Sized>(self, inner: NonNull<Inner<C, T>>) -> Layout {
+        // Safety: the metadata is that of a live allocation, and no reference to it is created.
+        Layout::for_value_raw(inner.as_ptr())
+    }
+
+    /// # Safety
+    ///
+    /// `self` must have been computed for `inner`, which must not have been freed yet.
+    #[cfg(not(feature = "nightly"))]
+    unsafe fn get<C, T: ?Sized>(self, _inner: NonNull<Inner<C, T>>) -> Layout {
+        self.layout
+    }
+}
+
+/// Frees an allocation made in `alloc` (or made by the global allocator, if `A` is `Global`),
+/// without dropping the data.
+///
+/// # Safety
+///
+/// The data must have already been dropped (or moved out), and there must be no other references
+/// to the allocation, which will be left dangling.  Additionally, `layout` must have been computed
+/// for this allocation, and `alloc` must be the allocator (or a clone of it) that made it.
+pub(crate) unsafe fn deallocate_in<C, T: ?Sized, A: Allocator>(
+    inner: NonNull<Inner<C, T>>,
+    layout: AllocLayout,
+    alloc: &A,
+) {
+    // The pointer was created by Box, allocate_for_layout() or new_in(), all of which use the
+    // layout that Layout::for_value() returns.
+    alloc.deallocate(inner.cast(), layout.get(inner));
+}
+
+/// Replaces the address of a (possibly wide) pointer, keeping its metadata.
+///
+/// With the `nightly` feature, the result is built from `data` and the metadata of `ptr`, so it
+/// also gets the provenance of `data`.
+#[cfg(feature = "nightly")]
+fn set_data_ptr<T: ?Sized, U>(ptr: *mut T, data: *mut U) -> *mut T {
+    ptr::from_raw_parts_mut(data as *mut u8, ptr::metadata(ptr))
+}
+
+/// Replaces the address of a (possibly wide) pointer, keeping its metadata.
+///
+/// Stable Rust cannot attach metadata to another pointer, so only the address word of a copy of
+/// `ptr` is overwritten with `data`, like std did before `ptr::from_raw_parts_mut()` existed; the
+/// result thus gets the provenance of `data`.  This relies on the address being the first word of
+/// wide pointers, which is how rustc lays out both slice and trait object pointers.
+#[cfg(not(feature = "nightly"))]
+fn set_data_ptr<T: ?Sized, U>(mut ptr: *mut T, data: *mut U) -> *mut T {
+    // Safety: writing a thin pointer over the first word of a (possibly wide) pointer only
+    // replaces its address.
+    unsafe { ptr::write(&mut ptr as *mut *mut T as *mut *mut u8, data as *mut u8) };
+    ptr
+}
@@ -1,75 +1,607 @@
-#![cfg_attr(feature = "nightly", feature(coerce_unsized, dropck_eyepatch, unsize))]
+#![cfg_attr(
+    feature = "nightly",
+    feature(
+        allocator_api,
+        coerce_unsized,
+        dropck_eyepatch,
+        layout_for_ptr,
+        ptr_metadata,
+        unsize
+    )
+)]
 
 use std::{
-    fmt::{Debug, Formatter, Result},
+    borrow::Borrow,
+    cmp::Ordering as CmpOrdering,
+    fmt::{self, Debug, Display, Formatter, Result},
+    hash::{Hash, Hasher},
+    iter::FromIterator,
     marker::PhantomData,
+    mem::{ManuallyDrop, MaybeUninit},
     ops::Deref,
-    ptr::NonNull,
-    sync::atomic::{AtomicUsize, Ordering},
+    pin::Pin,
+    ptr::{self, NonNull},
 };
 
+use allocator_api2::alloc::{Allocator, Global};
+use sync::AtomicUsize;
+
+// With the `loom` feature, the atomics (and spin loop hints) are replaced by loom's, so that the
+// orderings can be model checked by the tests in tests/loom.rs.
+mod sync {
+    #[cfg(feature = "loom")]
+    pub(crate) use loom::{
+        hint,
+        sync::atomic::{fence, AtomicBool, AtomicPtr, AtomicUsize, Ordering},
+    };
+
+    #[cfg(not(feature = "loom"))]
+    pub(crate) use std::{
+        hint,
+        sync::atomic::{fence, AtomicBool, AtomicPtr, AtomicUsize, Ordering},
+    };
+}
+
+mod inner;
+#[cfg(not(feature = "loom"))]
+mod rk;
+mod swap;
+mod unique;
+
+pub use inner::Counter;
+#[cfg(not(feature = "loom"))]
+pub use rk::{Rk, RkWeak};
+pub use swap::ArkSwap;
+pub use unique::UniqueArk;
+
+use inner::{AllocLayout, Inner, WEAK_LOCKED};
+
 #[cfg(feature = "nightly")]
 use std::{marker::Unsize, ops::CoerceUnsized};
 
-pub struct Ark<T: ?Sized> {
+/// A thread-safe reference counted pointer, with atomic counts.
+pub type Ark<T, A = Global> = Shared<AtomicUsize, T, A>;
+
+/// A weak reference to the data shared by one or more [`Ark`]s.
+///
+/// See [`SharedWeak`].
+pub type ArkWeak<T, A = Global> = SharedWeak<AtomicUsize, T, A>;
+
+/// A reference counted pointer, generic over the type `C` of its counts.
+///
+/// This implements both [`Ark`] (`C = AtomicUsize`) and [`Rk`] (`C = Cell<usize>`), which are the
+/// names meant to be used; the methods are documented in terms of `Ark`, but apply to `Rk` as
+/// well.
+pub struct Shared<C: Counter, T: ?Sized, A: Allocator = Global> {
     // Use the fact that the pointer is never null for a niche; this also makes Ark covariant over
-    // ArkInner<T>, which is not accomplished by `_phantom` bellow (FIXME why?!).
-    ptr: NonNull<ArkInner<T>>,
+    // Inner<C, T>, which is not accomplished by `_phantom` bellow (FIXME why?!).  Being a raw
+    // pointer, NonNull also makes Shared neither Send nor Sync, unless implemented below (only
+    // for Ark, whose counts are atomic).
+    ptr: NonNull<Inner<C, T>>,
 
     // Tell the compiler that even though we do not access T when *we* drop, we still drop
-    // ArkInner<T>, and T could still perform an access when it drops.  Because `ptr` is just a
-    // pointer, it is not sufficient for the drop checker to see that we (may) own an ArkInner<T>.
-    _phantom: PhantomData<ArkInner<T>>,
+    // Inner<C, T>, and T could still perform an access when it drops.  Because `ptr` is just a
+    // pointer, it is not sufficient for the drop checker to see that we (may) own an Inner<C, T>.
+    _phantom: PhantomData<Inner<C, T>>,
+
+    // The allocator that the allocation was made with, and that it will be freed with.  For the
+    // default Global allocator, this is zero sized.
+    alloc: A,
 }
 
-#[repr(C)]
-struct ArkInner<T: ?Sized> {
-    strong_count: AtomicUsize,
-    data: T,
+/// A weak reference to the data shared by one or more [`Shared`] pointers.
+///
+/// A weak reference does not keep the data alive, only its allocation, and must be upgraded back
+/// into a strong one to access the data.  This allows cyclic structures to be built without
+/// leaking them.
+pub struct SharedWeak<C: Counter, T: ?Sized, A: Allocator = Global> {
+    // Unlike Shared, SharedWeak does not own T, so no PhantomData is necessary.
+    ptr: NonNull<Inner<C, T>>,
+    alloc: A,
+
+    // What the last reference will need to free the allocation, since by then the data will have
+    // been dropped.
+    layout: AllocLayout,
 }
 
-impl<T> Ark<T> {
-    pub fn new(data: T) -> Ark<T> {
-        let inner = ArkInner {
-            strong_count: AtomicUsize::new(1),
-            data,
+// The weak_count is temporarily set to WEAK_LOCKED by Ark::is_unique(), to prevent new ArkWeaks
+// from being created while it checks strong_count.
+type ArkInner<T> = Inner<AtomicUsize, T>;
+
+impl<C: Counter, T> Shared<C, T> {
+    pub fn new(data: T) -> Self {
+        Shared::new_in(data, Global)
+    }
+
+    /// Constructs a new `Ark<T>` whose data can hold a weak reference to itself.
+    ///
+    /// `data_fn` is given an [`ArkWeak`] to the allocation that is being constructed, which it can
+    /// clone and store in the data (e.g. as a back pointer to the parent in a tree).  Until
+    /// `data_fn` returns, the weak reference cannot be upgraded.
+    pub fn new_cyclic<F: FnOnce(&SharedWeak<C, T>) -> T>(data_fn: F) -> Self {
+        let uninit: NonNull<Inner<C, MaybeUninit<T>>> = inner::new_uninit();
+
+        // Like in UniqueArk, strong_count is zero until the data is initialized, which prevents
+        // the weak references from upgrading; the weak reference collectively held by the strong
+        // references is held by `weak` in the meantime.  Pretending that the data is initialized
+        // is fine, since it cannot be accessed through weak references that cannot be upgraded.
+        //
+        // Safety: the allocation was just created, and MaybeUninit<T> needs no initialization.
+        unsafe { uninit.as_ref() }.strong_count.set(0);
+        let weak = SharedWeak {
+            ptr: uninit.cast(),
+            alloc: Global,
+            layout: unsafe { AllocLayout::of(uninit) },
         };
 
-        let ptr = Box::into_raw(Box::new(inner));
+        // If data_fn() panics, `weak` releases its weak reference, freeing the allocation (but
+        // not any ArkWeaks that data_fn() may have stashed elsewhere).
+        let data = data_fn(&weak);
 
-        // Safety: a pointer created with Box::into_raw() cannot be null.
-        Ark {
-            ptr: unsafe { NonNull::new_unchecked(ptr) },
+        // Safety: the allocation is still alive because of `weak`, and no one else can access
+        // the data until strong_count is set; `weak` is then forgotten, since its reference is
+        // now the one collectively held by the strong references.
+        //
+        // Ordering: set() synchronizes with the increment in upgrade(), so that a weak reference
+        // upgraded on another thread sees the initialized data.
+        unsafe {
+            ptr::write(&mut (*uninit.as_ptr()).data, MaybeUninit::new(data));
+            uninit.as_ref().strong_count.set(1);
+            Shared::from_inner(ManuallyDrop::new(weak).ptr)
+        }
+    }
+
+    /// Constructs a new `Ark` with uninitialized data.
+    ///
+    /// The data is allocated directly on the heap, so this can be used to build large values in
+    /// place, without first creating them on the stack; use [`Ark::get_mut`] (or [`UniqueArk`])
+    /// to initialize it, and [`Ark::assume_init`] once done.
+    pub fn new_uninit() -> Shared<C, MaybeUninit<T>> {
+        // Safety: the allocation was just created, and MaybeUninit<T> needs no initialization.
+        unsafe { Shared::from_inner(inner::new_uninit()) }
+    }
+
+    /// Constructs a new `Ark` slice with uninitialized elements.
+    ///
+    /// See [`Ark::new_uninit`].
+    pub fn new_uninit_slice(len: usize) -> Shared<C, [MaybeUninit<T>]> {
+        // Safety: see new_uninit().
+        unsafe { Shared::from_inner(inner::new_uninit_slice(len)) }
+    }
+
+    /// Constructs a new `Pin<Ark<T>>`.
+    ///
+    /// See [`Ark::into_pin`].
+    pub fn pin(data: T) -> Pin<Self> {
+        Shared::into_pin(Shared::new(data))
+    }
+}
+
+impl<C: Counter, T, A: Allocator> Shared<C, T, A> {
+    /// Constructs a new `Ark<T, A>` in the provided allocator.
+    ///
+    /// The allocation is freed through `alloc` (or one of its clones, made by [`Ark::clone`] or
+    /// [`Ark::downgrade`]) once the last `Ark` and [`ArkWeak`] to it are gone.
+    pub fn new_in(data: T, alloc: A) -> Self {
+        Shared {
+            ptr: inner::new_in(data, &alloc),
             _phantom: PhantomData,
+            alloc,
+        }
+    }
+
+    /// Returns the inner value, if this is the only `Ark` to it.
+    ///
+    /// Otherwise, an `Err` is returned with the same `Ark` that was passed in.  Note that this can
+    /// fail even if all other `Ark`s are being concurrently dropped or unwrapped; when that is not
+    /// desired, use [`Ark::into_inner`].
+    pub fn try_unwrap(this: Self) -> std::result::Result<T, Self> {
+        // Ordering: compare_and_set() synchronizes with every other (now dropped) Ark's
+        // drop_impl(), so all accesses they made to the data happen before we move it out; this
+        // is the same reasoning as in drop_impl().
+        if !this.inner().strong_count.compare_and_set(1, 0) {
+            return Err(this);
         }
+
+        let this = ManuallyDrop::new(this);
+
+        // Safety: strong_count was one and is now zero, so we were the last Ark and the data will
+        // never be accessed again through this allocation (ArkWeak::upgrade() fails from now on);
+        // it is therefore safe to move it out without dropping it in place.  The allocator is
+        // moved out as well, since `this` will not be dropped.
+        let (layout, data, alloc) = unsafe {
+            (
+                AllocLayout::of(this.ptr),
+                ptr::read(&this.inner().data),
+                ptr::read(&this.alloc),
+            )
+        };
+
+        // Release the weak reference collectively held by all strong references.
+        drop(SharedWeak {
+            ptr: this.ptr,
+            alloc,
+            layout,
+        });
+
+        Ok(data)
+    }
+
+    /// Returns the inner value, if this is the last `Ark` to it.
+    ///
+    /// Unlike [`Ark::try_unwrap`], when several `Ark`s to the same allocation are concurrently
+    /// passed to this function, it is guaranteed that exactly one of them returns the value.
+    pub fn into_inner(this: Self) -> Option<T> {
+        let this = ManuallyDrop::new(this);
+
+        // Ordering: exactly the same as in drop_impl(), which this mirrors except for moving the
+        // data out instead of dropping it in place.
+        if !this.inner().strong_count.decrement() {
+            return None;
+        }
+
+        // Safety: see try_unwrap().
+        let (layout, data, alloc) = unsafe {
+            (
+                AllocLayout::of(this.ptr),
+                ptr::read(&this.inner().data),
+                ptr::read(&this.alloc),
+            )
+        };
+
+        // Release the weak reference collectively held by all strong references.
+        drop(SharedWeak {
+            ptr: this.ptr,
+            alloc,
+            layout,
+        });
+
+        Some(data)
+    }
+}
+
+impl<C: Counter, T> Shared<C, MaybeUninit<T>> {
+    /// Converts to `Ark<T>`.
+    ///
+    /// # Safety
+    ///
+    /// The data must have been initialized.
+    pub unsafe fn assume_init(self) -> Shared<C, T> {
+        let this = ManuallyDrop::new(self);
+
+        // MaybeUninit<T> has the same layout as T.
+        Shared::from_inner(this.ptr.cast())
+    }
+}
+
+impl<C: Counter, T> Shared<C, [MaybeUninit<T>]> {
+    /// Converts to `Ark<[T]>`.
+    ///
+    /// # Safety
+    ///
+    /// All elements must have been initialized.
+    pub unsafe fn assume_init(self) -> Shared<C, [T]> {
+        let this = ManuallyDrop::new(self);
+
+        // MaybeUninit<T> has the same layout as T, and casting keeps the length metadata.
+        Shared::from_inner(NonNull::new_unchecked(
+            this.ptr.as_ptr() as *mut Inner<C, [T]>
+        ))
+    }
+}
+
+impl<C: Counter, T: Clone, A: Allocator + Clone> Shared<C, T, A> {
+    /// Makes a mutable reference to the data, cloning it first if necessary (clone-on-write).
+    ///
+    /// If there are other `Ark`s to the same allocation, the data is cloned into a new allocation
+    /// that `this` then points to.  If there are only `ArkWeak`s left, the data is moved (not
+    /// cloned) into a new allocation, and the `ArkWeak`s are left unable to upgrade.
+    pub fn make_mut(this: &mut Self) -> &mut T {
+        // Clone the allocator for the new allocation before touching the counts or moving anything
+        // out of `this`, so that a panic in A::clone() leaves everything as it was.
+        let alloc = this.alloc.clone();
+
+        // Ordering: compare_and_set() synchronizes with every other (now dropped) Ark's
+        // drop_impl(), so that all of their accesses to the data happen before ours.  Temporarily
+        // setting strong_count to zero also prevents any ArkWeak from upgrading while we check
+        // weak_count.
+        if !this.inner().strong_count.compare_and_set(1, 0) {
+            // There are other Arks, so we must clone.
+            *this = Shared::new_in((**this).clone(), alloc);
+        } else if this.inner().weak_count.get() != 1 {
+            // We were the only Ark, but there are still ArkWeaks around.  Since strong_count is
+            // now zero, they cannot upgrade anymore, so we can steal the data and leave them with
+            // the old allocation.
+
+            // Safety: we hold the only (former) strong reference and the ArkWeaks cannot access
+            // the data, so it is safe to move it out; the old Ark is then overwritten without
+            // being dropped, because its share of the allocation (and its allocator) is released
+            // by `weak` instead.
+            unsafe {
+                let weak = SharedWeak {
+                    ptr: this.ptr,
+                    alloc: ptr::read(&this.alloc),
+                    layout: AllocLayout::of(this.ptr),
+                };
+                let data = ptr::read(&this.inner().data);
+                ptr::write(this, Shared::new_in(data, alloc));
+                drop(weak);
+            }
+        } else {
+            // We were the only reference of any kind, so just restore strong_count.
+            //
+            // Ordering: set() ensures that, if this Ark is later cloned into other threads, our
+            // upcoming writes to the data are visible to them.
+            this.inner().strong_count.set(1);
+        }
+
+        // Safety: we now hold the only reference of any kind to the allocation.
+        unsafe { Shared::get_mut_unchecked(this) }
     }
 }
 
-impl<T: ?Sized> Ark<T> {
-    fn inner(&self) -> &ArkInner<T> {
-        // Safety: as the pointer was created with Box::into_raw(), we know that it is correctly
+impl<C: Counter, T: ?Sized, A: Allocator> Shared<C, T, A> {
+    fn inner(&self) -> &Inner<C, T> {
+        // Safety: as the pointer was created by the inner module, we know that it is correctly
         // aligned, dereferenceable and that the value it points to is initialized.  Additionally,
         // it is valid as long as the strong_count is greater than zero, and we know that it must
-        // be at least one because of our own &self reference.  And the aliasing is always
-        // respected because we never mutate or take a mutable reference from it.
+        // be at least one because of our own &self reference.  And the aliasing is respected
+        // because mutable references to the data are only handed out (by get_mut() and
+        // make_mut()) when there are no other references to the allocation.
         unsafe { self.ptr.as_ref() }
     }
+
+    /// # Safety
+    ///
+    /// There must be no other `Ark` or `ArkWeak` pointers to the same allocation for the duration
+    /// of the returned borrow.
+    unsafe fn get_mut_unchecked(this: &mut Self) -> &mut T {
+        &mut (*this.ptr.as_ptr()).data
+    }
+
+    /// Returns a mutable reference to the data, if there are no other `Ark` or `ArkWeak` pointers
+    /// to the same allocation.
+    pub fn get_mut(this: &mut Self) -> Option<&mut T> {
+        if this.is_unique() {
+            // Safety: just checked that we are the only reference to the allocation, and the
+            // &mut Self borrow prevents new ones from being created.
+            Some(unsafe { Shared::get_mut_unchecked(this) })
+        } else {
+            None
+        }
+    }
+
+    // Checks whether this is the only reference of any kind to the allocation.
+    fn is_unique(&self) -> bool {
+        // Lock weak_count while checking strong_count: otherwise, one of our ArkWeaks could be
+        // concurrently upgraded and its Ark downgraded and then dropped between our two loads,
+        // and we would observe both counts at one despite the existence of a new ArkWeak.
+        //
+        // Ordering: compare_and_set() synchronizes with weak_drop_impl(), so that any accesses
+        // made by the former ArkWeaks happen before ours.
+        if self.inner().weak_count.compare_and_set(1, WEAK_LOCKED) {
+            // Ordering: get() synchronizes with drop_impl(), for the same reason as above.
+            let unique = self.inner().strong_count.get() == 1;
+
+            // Ordering: set() synchronizes with the increment in downgrade().
+            self.inner().weak_count.set(1);
+
+            unique
+        } else {
+            false
+        }
+    }
+
+    /// Converts an `Ark<T>` into a `Pin<Ark<T>>`, even if `T` is not `Unpin`.
+    ///
+    /// This is always sound because the data is never moved out of its allocation while there are
+    /// strong references to it, and the only ways to move it out (like [`Ark::try_unwrap`]) or to
+    /// get a mutable reference to it require an unpinned `Ark`.
+    pub fn into_pin(this: Self) -> Pin<Self> {
+        // Safety: see above.
+        unsafe { Pin::new_unchecked(this) }
+    }
+
+    /// Returns a pointer to the data, without affecting the reference count.
+    ///
+    /// The pointer is valid for as long as there are strong references to the allocation.
+    pub fn as_ptr(this: &Self) -> *const T {
+        // Going through self.ptr (instead of Deref) ensures that the resulting pointer is allowed
+        // to access the whole allocation, which from_raw() needs.
+        inner::data_ptr(this.ptr)
+    }
+
+    /// Returns a reference to the allocator of this `Ark`.
+    pub fn allocator(this: &Self) -> &A {
+        &this.alloc
+    }
 }
 
-impl<T: ?Sized> Clone for Ark<T> {
-    fn clone(&self) -> Ark<T> {
-        // Ordering: there are no access to synchronize in this function and the inner struct is
-        // not going anywhere because the &self reference implies that strong_count >= 1.
-        self.inner().strong_count.fetch_add(1, Ordering::Relaxed);
+// Raw pointers only carry the address of the data, so they can only be used with the Global
+// allocator, which does not need to be stored anywhere.
+impl<C: Counter, T: ?Sized> Shared<C, T> {
+    /// Consumes the `Ark`, returning a pointer to the data.
+    ///
+    /// The strong reference is transferred to the pointer, and to avoid a leak it must be turned
+    /// back into an `Ark` with [`Ark::from_raw`] (or released with
+    /// [`Ark::decrement_strong_count`]).
+    pub fn into_raw(this: Self) -> *const T {
+        let ptr = Shared::as_ptr(&this);
+        std::mem::forget(this);
+        ptr
+    }
+
+    /// Reconstructs an `Ark` from a pointer returned by [`Ark::into_raw`].
+    ///
+    /// # Safety
+    ///
+    /// `ptr` must have been returned by `Ark::<U>::into_raw`, where `U` has the same size and
+    /// alignment as `T` (or `U` is `T` itself), and it must still own the strong reference it
+    /// was created with.  Each such reference can only be reclaimed once.
+    pub unsafe fn from_raw(ptr: *const T) -> Self {
+        // The data is still alive, since the pointer must still own a strong reference.
+        Shared::from_inner(inner::from_data_ptr(ptr))
+    }
+
+    /// Increments the strong reference count behind a pointer returned by [`Ark::into_raw`].
+    ///
+    /// # Safety
+    ///
+    /// `ptr` must have been returned by [`Ark::into_raw`], and the strong reference count must be
+    /// at least one (i.e. the allocation must still be alive) for the duration of this call.
+    pub unsafe fn increment_strong_count(ptr: *const T) {
+        // Don't let the temporary Ark release the reference that it does not really own.
+        let this = ManuallyDrop::new(Self::from_raw(ptr));
+        let _ = ManuallyDrop::new(Shared::clone(&this));
+    }
 
-        Ark {
+    /// Decrements the strong reference count behind a pointer returned by [`Ark::into_raw`],
+    /// possibly dropping the data.
+    ///
+    /// # Safety
+    ///
+    /// `ptr` must have been returned by [`Ark::into_raw`], and it must own the strong reference
+    /// that is released (e.g. because of a previous call to [`Ark::increment_strong_count`]).
+    pub unsafe fn decrement_strong_count(ptr: *const T) {
+        drop(Self::from_raw(ptr));
+    }
+}
+
+impl<C: Counter, T: ?Sized, A: Allocator> Shared<C, T, A> {
+    /// Creates a new [`ArkWeak`] pointer to this allocation.
+    pub fn downgrade(this: &Self) -> SharedWeak<C, T, A>
+    where
+        A: Clone,
+    {
+        // While weak_count is locked by is_unique() we must wait (it will be unlocked soon); the
+        // allocation is not going anywhere because the &Self reference implies that
+        // strong_count >= 1 (and, therefore, that weak_count >= 1).
+        this.inner().weak_count.increment_unless_locked();
+
+        SharedWeak {
+            ptr: this.ptr,
+            alloc: this.alloc.clone(),
+            // Safety: the data is alive, since we are a strong reference.
+            layout: unsafe { AllocLayout::of(this.ptr) },
+        }
+    }
+
+    /// Returns `true` if both `Ark`s point to the same allocation.
+    ///
+    /// Only the addresses are compared, ignoring any metadata (like vtables) of wide pointers.
+    pub fn ptr_eq(this: &Self, other: &Self) -> bool {
+        ptr::addr_eq(this.ptr.as_ptr(), other.ptr.as_ptr())
+    }
+
+    /// Gets the number of [`Ark`] pointers to this allocation.
+    pub fn strong_count(this: &Self) -> usize {
+        this.inner().strong_count.get()
+    }
+
+    /// Gets the number of [`ArkWeak`] pointers to this allocation.
+    pub fn weak_count(this: &Self) -> usize {
+        let weak = this.inner().weak_count.get();
+
+        // If weak_count is locked, is_unique() is being called on another Ark, which can only
+        // happen if there were no ArkWeaks (when it took the lock).  Otherwise, discount the weak
+        // reference collectively held by the strong references, which must still exist because
+        // of `this`.
+        if weak == WEAK_LOCKED {
+            0
+        } else {
+            weak - 1
+        }
+    }
+}
+
+impl<C: Counter, T: ?Sized, A: Allocator> SharedWeak<C, T, A> {
+    fn strong_count_ref(&self) -> &C {
+        // Safety: the allocation is valid as long as weak_count is greater than zero, which our
+        // own &self reference guarantees.  But the data may have already been dropped, so avoid
+        // creating a reference to the entire Inner<C, T>.
+        unsafe { &(*self.ptr.as_ptr()).strong_count }
+    }
+
+    fn weak_count_ref(&self) -> &C {
+        // Safety: see strong_count_ref().
+        unsafe { &(*self.ptr.as_ptr()).weak_count }
+    }
+
+    /// Attempts to upgrade into an [`Ark`], returning `None` if the data has already been dropped.
+    pub fn upgrade(&self) -> Option<Shared<C, T, A>>
+    where
+        A: Clone,
+    {
+        // strong_count must never be incremented from zero, since the data has been (or is being)
+        // dropped by then.
+        if !self.strong_count_ref().increment_unless_zero() {
+            return None;
+        }
+
+        Some(Shared {
             ptr: self.ptr,
             _phantom: PhantomData,
+            alloc: self.alloc.clone(),
+        })
+    }
+
+    /// Returns `true` if both `ArkWeak`s point to the same allocation.
+    ///
+    /// See [`Ark::ptr_eq`].
+    pub fn ptr_eq(&self, other: &Self) -> bool {
+        ptr::addr_eq(self.ptr.as_ptr(), other.ptr.as_ptr())
+    }
+
+    /// Gets the number of [`Ark`] pointers to this allocation.
+    pub fn strong_count(&self) -> usize {
+        self.strong_count_ref().get()
+    }
+
+    /// Gets the number of [`ArkWeak`] pointers to this allocation, or zero if there are no
+    /// remaining [`Ark`] pointers.
+    pub fn weak_count(&self) -> usize {
+        let weak = self.weak_count_ref().get();
+
+        // Once the last Ark is dropped, upgrade() can never succeed, so report no weak references
+        // at all (like std::sync::Weak).  Otherwise discount the weak reference collectively held
+        // by the strong references.
+        if self.strong_count() > 0 {
+            weak - 1
+        } else {
+            0
         }
     }
 }
 
-impl<T: ?Sized> Deref for Ark<T> {
+impl<C: Counter, T: ?Sized, A: Allocator + Clone> Clone for SharedWeak<C, T, A> {
+    fn clone(&self) -> Self {
+        // Unlike Ark::downgrade(), this cannot race with is_unique(), which only locks weak_count
+        // when there are no ArkWeaks.
+        self.weak_count_ref().increment();
+
+        SharedWeak {
+            ptr: self.ptr,
+            alloc: self.alloc.clone(),
+            layout: self.layout,
+        }
+    }
+}
+
+impl<C: Counter, T: ?Sized, A: Allocator + Clone> Clone for Shared<C, T, A> {
+    fn clone(&self) -> Self {
+        // The inner struct is not going anywhere because the &self reference implies that
+        // strong_count >= 1.
+        self.inner().strong_count.increment();
+
+        Shared {
+            ptr: self.ptr,
+            _phantom: PhantomData,
+            alloc: self.alloc.clone(),
+        }
+    }
+}
+
+impl<C: Counter, T: ?Sized, A: Allocator> Deref for Shared<C, T, A> {
     type Target = T;
 
     fn deref(&self) -> &Self::Target {
@@ -77,63 +609,329 @@ impl<T: ?Sized> Deref for Ark<T> {
     }
 }
 
+// Constructors for dynamically sized types, which Ark::new() cannot handle because the data must
+// be moved into Inner<C, T> by value.  These work on stable Rust, and can also be used to get an
+// Ark<dyn Trait> without the nightly CoerceUnsized support:
+//
+//     let a: Ark<dyn Debug> = Ark::from(Box::new(42) as Box<dyn Debug>);
+
+impl<C: Counter, T: ?Sized> Shared<C, T> {
+    /// # Safety
+    ///
+    /// `inner` must have been allocated by one of the functions in the `inner` module, have its
+    /// data initialized, and own a strong reference.
+    unsafe fn from_inner(inner: NonNull<Inner<C, T>>) -> Self {
+        Shared {
+            ptr: inner,
+            _phantom: PhantomData,
+            alloc: Global,
+        }
+    }
+}
+
+impl<C: Counter, T> From<Vec<T>> for Shared<C, [T]> {
+    fn from(v: Vec<T>) -> Self {
+        // Safety: the allocation was just created and initialized.
+        unsafe { Shared::from_inner(inner::from_vec(v)) }
+    }
+}
+
+impl<C: Counter, T: Clone> From<&[T]> for Shared<C, [T]> {
+    fn from(v: &[T]) -> Self {
+        // Safety: see From<Vec<T>>.
+        unsafe { Shared::from_inner(inner::from_slice(v)) }
+    }
+}
+
+impl<C: Counter> From<String> for Shared<C, str> {
+    fn from(s: String) -> Self {
+        // Safety: see From<Vec<T>>; the bytes came from a String and so are valid UTF-8.
+        unsafe { Shared::from_inner(inner::bytes_to_str(inner::from_vec(s.into_bytes()))) }
+    }
+}
+
+impl<C: Counter> From<&str> for Shared<C, str> {
+    fn from(s: &str) -> Self {
+        // Safety: see From<String>.
+        unsafe { Shared::from_inner(inner::bytes_to_str(inner::from_slice(s.as_bytes()))) }
+    }
+}
+
+impl<C: Counter, T: ?Sized> From<Box<T>> for Shared<C, T> {
+    fn from(b: Box<T>) -> Self {
+        // Safety: see From<Vec<T>>.
+        unsafe { Shared::from_inner(inner::from_box(b)) }
+    }
+}
+
 #[cfg(feature = "nightly")]
-unsafe impl<#[may_dangle] T: ?Sized> Drop for Ark<T> {
+unsafe impl<C: Counter, #[may_dangle] T: ?Sized, A: Allocator> Drop for Shared<C, T, A> {
     fn drop(&mut self) {
         drop_impl(self);
     }
 }
 #[cfg(not(feature = "nightly"))]
-impl<T: ?Sized> Drop for Ark<T> {
+impl<C: Counter, T: ?Sized, A: Allocator> Drop for Shared<C, T, A> {
     fn drop(&mut self) {
         drop_impl(self);
     }
 }
 
-fn drop_impl<T: ?Sized>(this: &mut Ark<T>) {
-    // Ordering: the box must only be dropped after the store to strong_count.
-    if this.inner().strong_count.fetch_sub(1, Ordering::AcqRel) == 1 {
-        // Safety: pointer was created with Box::into_raw(), and is valid because strong_count
-        // was still one; dropping he reconstructed Box is also safe because, since we are the
-        // last Ark, this.ptr will not be used again and be left dangling.
-        let _ = unsafe { Box::from_raw(this.ptr.as_ptr()) };
+fn drop_impl<C: Counter, T: ?Sized, A: Allocator>(this: &mut Shared<C, T, A>) {
+    // Ordering: decrement() makes the accesses of all other Arks happen before the data is
+    // dropped.
+    if this.inner().strong_count.decrement() {
+        // Safety: pointer was created by the inner module, and is valid because strong_count
+        // was still one; dropping the data in place is also safe because, since we are the last
+        // Ark, the data will not be accessed again (ArkWeak::upgrade() fails from now on).  The
+        // layout of the allocation must be computed before the data is gone.
+        let layout = unsafe {
+            let layout = AllocLayout::of(this.ptr);
+            ptr::drop_in_place(&mut (*this.ptr.as_ptr()).data);
+            layout
+        };
+
+        // Release the weak reference collectively held by all strong references, possibly
+        // deallocating Inner<C, T> if there are no ArkWeaks left.  The allocator is borrowed,
+        // since it is still owned (and will be dropped) by `this`.
+        drop(SharedWeak {
+            ptr: this.ptr,
+            alloc: &this.alloc,
+            layout,
+        });
     }
     // TODO possibly optimize for the case where drop does *not* drop the contents
 }
 
-impl<T: Debug> Debug for Ark<T> {
+#[cfg(feature = "nightly")]
+unsafe impl<C: Counter, #[may_dangle] T: ?Sized, A: Allocator> Drop for SharedWeak<C, T, A> {
+    fn drop(&mut self) {
+        weak_drop_impl(self);
+    }
+}
+#[cfg(not(feature = "nightly"))]
+impl<C: Counter, T: ?Sized, A: Allocator> Drop for SharedWeak<C, T, A> {
+    fn drop(&mut self) {
+        weak_drop_impl(self);
+    }
+}
+
+fn weak_drop_impl<C: Counter, T: ?Sized, A: Allocator>(this: &mut SharedWeak<C, T, A>) {
+    // Ordering: decrement() makes the accesses made by the other (weak or strong) references,
+    // including dropping the data, happen before the allocation is freed.
+    if this.weak_count_ref().decrement() {
+        // Safety: the data has already been dropped (by the last Ark), and since we are the last
+        // reference of any kind, this.ptr will not be used again and be left dangling.
+        unsafe { inner::deallocate_in(this.ptr, this.layout, &this.alloc) };
+    }
+}
+
+impl<C: Counter, T: Debug + ?Sized, A: Allocator> Debug for Shared<C, T, A> {
     fn fmt(&self, f: &mut Formatter<'_>) -> Result {
-        let inner = unsafe { self.ptr.as_ref() };
-        inner.data.fmt(f)
+        Debug::fmt(&**self, f)
+    }
+}
+
+impl<C: Counter, T: Display + ?Sized, A: Allocator> Display for Shared<C, T, A> {
+    fn fmt(&self, f: &mut Formatter<'_>) -> Result {
+        Display::fmt(&**self, f)
+    }
+}
+
+// Formats the address of the data, not of the whole allocation, so that it matches Ark::as_ptr().
+impl<C: Counter, T: ?Sized, A: Allocator> fmt::Pointer for Shared<C, T, A> {
+    fn fmt(&self, f: &mut Formatter<'_>) -> Result {
+        fmt::Pointer::fmt(&Shared::as_ptr(self), f)
+    }
+}
+
+// Comparisons and hashing are forwarded to the data, and not based on the pointers; for that,
+// use Ark::ptr_eq().
+
+impl<C: Counter, T: PartialEq + ?Sized, A: Allocator> PartialEq for Shared<C, T, A> {
+    fn eq(&self, other: &Self) -> bool {
+        **self == **other
+    }
+}
+
+impl<C: Counter, T: Eq + ?Sized, A: Allocator> Eq for Shared<C, T, A> {}
+
+impl<C: Counter, T: PartialOrd + ?Sized, A: Allocator> PartialOrd for Shared<C, T, A> {
+    fn partial_cmp(&self, other: &Self) -> Option<CmpOrdering> {
+        (**self).partial_cmp(&**other)
+    }
+}
+
+impl<C: Counter, T: Ord + ?Sized, A: Allocator> Ord for Shared<C, T, A> {
+    fn cmp(&self, other: &Self) -> CmpOrdering {
+        (**self).cmp(&**other)
+    }
+}
+
+impl<C: Counter, T: Hash + ?Sized, A: Allocator> Hash for Shared<C, T, A> {
+    fn hash<H: Hasher>(&self, state: &mut H) {
+        (**self).hash(state)
+    }
+}
+
+impl<C: Counter, T: ?Sized, A: Allocator> Borrow<T> for Shared<C, T, A> {
+    fn borrow(&self) -> &T {
+        self
+    }
+}
+
+impl<C: Counter, T: ?Sized, A: Allocator> AsRef<T> for Shared<C, T, A> {
+    fn as_ref(&self) -> &T {
+        self
+    }
+}
+
+impl<C: Counter, T: Default> Default for Shared<C, T> {
+    fn default() -> Self {
+        Shared::new(T::default())
+    }
+}
+
+impl<C: Counter> Default for Shared<C, str> {
+    fn default() -> Self {
+        Shared::from("")
+    }
+}
+
+impl<C: Counter, T> Default for Shared<C, [T]> {
+    fn default() -> Self {
+        Shared::from(Vec::new())
+    }
+}
+
+impl<C: Counter, T> From<T> for Shared<C, T> {
+    fn from(data: T) -> Self {
+        Shared::new(data)
+    }
+}
+
+impl<C: Counter, T> FromIterator<T> for Shared<C, [T]> {
+    fn from_iter<I: IntoIterator<Item = T>>(iter: I) -> Self {
+        // Collecting into a Vec first is simpler, and the extra copy is cheap compared to the
+        // allocations made while collecting an iterator of unknown length anyway.
+        Shared::from(iter.into_iter().collect::<Vec<T>>())
+    }
+}
+
+// The data never moves while there are Arks to it, so Ark<T> is Unpin regardless of T; pinning
+// the data itself requires Pin<Ark<T>> (see Ark::into_pin()).
+impl<C: Counter, T: ?Sized, A: Allocator> Unpin for Shared<C, T, A> {}
+
+#[cfg(feature = "serde")]
+impl<C: Counter, T: serde::Serialize + ?Sized> serde::Serialize for Shared<C, T> {
+    fn serialize<S: serde::Serializer>(
+        &self,
+        serializer: S,
+    ) -> std::result::Result<S::Ok, S::Error> {
+        (**self).serialize(serializer)
+    }
+}
+
+// Going through Box<T> allows deserializing unsized types, like Ark<str> and Ark<[T]>, too.
+#[cfg(feature = "serde")]
+impl<'de, C: Counter, T: ?Sized> serde::Deserialize<'de> for Shared<C, T>
+where
+    Box<T>: serde::Deserialize<'de>,
+{
+    fn deserialize<D: serde::Deserializer<'de>>(
+        deserializer: D,
+    ) -> std::result::Result<Self, D::Error> {
+        Box::<T>::deserialize(deserializer).map(Shared::from)
+    }
+}
+
+impl<C: Counter, T: ?Sized, A: Allocator> Debug for SharedWeak<C, T, A> {
+    fn fmt(&self, f: &mut Formatter<'_>) -> Result {
+        write!(f, "(Weak)")
     }
 }
 
 // T must be Sync because Ark is used precisely to share references across threads
-unsafe impl<T: Send + Sync + ?Sized> Send for Ark<T> {}
+unsafe impl<T: Send + Sync + ?Sized, A: Allocator + Send> Send for Ark<T, A> {}
 
 // Ark<T> can be used inside another Ark
-unsafe impl<T: Send + Sync + ?Sized> Sync for Ark<T> {}
+unsafe impl<T: Send + Sync + ?Sized, A: Allocator + Sync> Sync for Ark<T, A> {}
+
+// ArkWeak<T> can be upgraded into an Ark<T>, so it must have the same bounds
+unsafe impl<T: Send + Sync + ?Sized, A: Allocator + Send> Send for ArkWeak<T, A> {}
+unsafe impl<T: Send + Sync + ?Sized, A: Allocator + Sync> Sync for ArkWeak<T, A> {}
 
-// FIXME (copied from Arc without much thought)
-unsafe impl<T: Send + Sync + ?Sized> Send for ArkInner<T> {}
-unsafe impl<T: Send + Sync + ?Sized> Sync for ArkInner<T> {}
+// Inner<C, T> is only ever accessed through Shared and SharedWeak, which already carry the
+// necessary bounds, so it does not need manual (and previously overly broad) Send/Sync impls: the
+// automatically derived ones (AtomicUsize is Send + Sync) are enough.
 
 #[cfg(feature = "nightly")]
-impl<T: ?Sized + Unsize<U>, U: ?Sized> CoerceUnsized<Ark<U>> for Ark<T> {}
+impl<C: Counter, T: ?Sized + Unsize<U>, U: ?Sized, A: Allocator> CoerceUnsized<Shared<C, U, A>>
+    for Shared<C, T, A>
+{
+}
 
-#[cfg(test)]
+#[cfg(feature = "nightly")]
+impl<C: Counter, T: ?Sized + Unsize<U>, U: ?Sized, A: Allocator> CoerceUnsized<SharedWeak<C, U, A>>
+    for SharedWeak<C, T, A>
+{
+}
+
+// The unit tests use atomics outside of a loom model, which loom does not allow.
+#[cfg(all(test, not(feature = "loom")))]
 mod tests {
     use super::*;
+    use crate::{inner::MAX_REFCOUNT, sync::Ordering};
 
     #[test]
-    #[cfg_attr(not(feature = "nightly"), ignore)]
     fn can_hold_a_trait_object() {
+        let a: Ark<dyn Debug> = Ark::from(Box::new(3) as Box<dyn Debug>);
+        assert_eq!(format!("{:?}", &*a), "3");
+
         #[cfg(feature = "nightly")]
         {
             let _: Ark<dyn Debug> = Ark::new(3);
         }
     }
 
+    #[test]
+    fn can_hold_slices_and_strs() {
+        let a: Ark<[String]> = Ark::from(vec![String::from("a"), String::from("b")]);
+        let b: Ark<[String]> = Ark::from(&a[..]);
+        assert_eq!(&*a, &*b);
+
+        let s: Ark<str> = Ark::from(String::from("hi"));
+        let t: Ark<str> = Ark::from("hi");
+        assert_eq!(&*s, &*t);
+
+        let e: Ark<[u64]> = Ark::from(Vec::new());
+        assert!(e.is_empty());
+    }
+
+    #[test]
+    fn unsized_data_is_dropped_and_freed() {
+        use std::sync::atomic::AtomicBool;
+
+        struct SetOnDrop<'a>(&'a AtomicBool);
+
+        impl Drop for SetOnDrop<'_> {
+            fn drop(&mut self) {
+                self.0.store(true, Ordering::Relaxed);
+            }
+        }
+
+        trait Nothing {}
+        impl Nothing for SetOnDrop<'_> {}
+
+        let dropped = AtomicBool::new(false);
+        let a: Ark<dyn Nothing> = Ark::from(Box::new(SetOnDrop(&dropped)) as Box<dyn Nothing>);
+        let w = Ark::downgrade(&a);
+
+        drop(a);
+        assert!(dropped.load(Ordering::Relaxed));
+        assert!(w.upgrade().is_none());
+    }
+
     #[test]
     fn allows_option_of_ark_to_use_niche() {
         use std::mem::size_of;
@@ -141,15 +939,214 @@ mod tests {
         // Because we ensure the compiler that the pointer is never null, it can use that as a
         // niche to optimize the layout of things like Option<Ark<T>>.
         assert_eq!(size_of::<Ark<&str>>(), size_of::<Option<Ark<&str>>>());
+        assert_eq!(
+            size_of::<ArkWeak<&str>>(),
+            size_of::<Option<ArkWeak<&str>>>()
+        );
     }
 
     #[test]
+    #[allow(clippy::extra_unused_lifetimes)]
     fn is_covariant_over_the_type_parameter<'a>() {
         let s: Ark<&'static str> = Ark::new("hi");
 
         // If Ark<T> had not been made covariant over T using std::ptr::NonNull, the next statement
         // would not compile.
+        let w: ArkWeak<&'static str> = Ark::downgrade(&s);
+
         let _: Ark<&'a str> = s;
+        let _: ArkWeak<&'a str> = w;
+    }
+
+    #[test]
+    fn weak_upgrades_only_while_strong_references_exist() {
+        let a = Ark::new(String::from("hi"));
+        let w = Ark::downgrade(&a);
+
+        assert_eq!(Ark::strong_count(&a), 1);
+        assert_eq!(Ark::weak_count(&a), 1);
+
+        let b = w.upgrade().unwrap();
+        assert_eq!(*b, "hi");
+        assert_eq!(w.strong_count(), 2);
+        assert_eq!(w.weak_count(), 1);
+
+        drop(a);
+        drop(b);
+
+        assert!(w.upgrade().is_none());
+        assert_eq!(w.strong_count(), 0);
+        assert_eq!(w.weak_count(), 0);
+    }
+
+    #[test]
+    fn weak_references_break_cycles() {
+        use std::{cell::RefCell, sync::atomic::AtomicBool};
+
+        struct Node<'a> {
+            parent: RefCell<Option<ArkWeak<Node<'a>>>>,
+            children: RefCell<Vec<Ark<Node<'a>>>>,
+            dropped: &'a AtomicBool,
+        }
+
+        impl Drop for Node<'_> {
+            fn drop(&mut self) {
+                self.dropped.store(true, Ordering::Relaxed);
+            }
+        }
+
+        let (parent_dropped, child_dropped) = (AtomicBool::new(false), AtomicBool::new(false));
+
+        let parent = Ark::new(Node {
+            parent: RefCell::new(None),
+            children: RefCell::new(vec![]),
+            dropped: &parent_dropped,
+        });
+        let child = Ark::new(Node {
+            parent: RefCell::new(Some(Ark::downgrade(&parent))),
+            children: RefCell::new(vec![]),
+            dropped: &child_dropped,
+        });
+        parent.children.borrow_mut().push(child.clone());
+
+        let weak_child = Ark::downgrade(&child);
+        drop(child);
+
+        let child = weak_child.upgrade().unwrap();
+        let child_parent = child.parent.borrow().as_ref().unwrap().upgrade().unwrap();
+        assert!(Ark::ptr_eq(&child_parent, &parent));
+        drop((child, child_parent));
+        assert!(!child_dropped.load(Ordering::Relaxed));
+
+        drop(parent);
+
+        assert!(weak_child.upgrade().is_none());
+        assert!(parent_dropped.load(Ordering::Relaxed));
+        assert!(child_dropped.load(Ordering::Relaxed));
+    }
+
+    #[test]
+    fn ptr_eq_compares_allocations() {
+        let a = Ark::new(42);
+        let b = Ark::new(42);
+
+        assert!(Ark::ptr_eq(&a, &a.clone()));
+        assert!(!Ark::ptr_eq(&a, &b));
+        assert!(Ark::downgrade(&a).ptr_eq(&Ark::downgrade(&a)));
+        assert!(!Ark::downgrade(&a).ptr_eq(&Ark::downgrade(&b)));
+
+        // Metadata is ignored.
+        let c: Ark<dyn Debug> = Ark::from(Box::new(42) as Box<dyn Debug>);
+        let d = Ark::from(Box::new(42) as Box<dyn Debug>);
+        assert!(Ark::ptr_eq(&c, &c.clone()));
+        assert!(!Ark::ptr_eq(&c, &d));
+    }
+
+    // Sets the counts of `a` as if `n - 1` strong (and weak) references had been leaked, so that
+    // the overflow checks can be tested without actually cloning 2^63 times.
+    fn seed_counts(a: &Ark<i32>, n: usize) {
+        a.inner().strong_count.store(n, Ordering::Relaxed);
+        a.inner().weak_count.store(n, Ordering::Relaxed);
+    }
+
+    #[test]
+    fn counts_can_reach_max_refcount() {
+        let a = Ark::new(42);
+
+        seed_counts(&a, MAX_REFCOUNT);
+        let b = a.clone();
+        let w = Ark::downgrade(&a);
+        assert_eq!(Ark::strong_count(&a), MAX_REFCOUNT + 1);
+        assert_eq!(Ark::weak_count(&a), MAX_REFCOUNT);
+
+        seed_counts(&a, MAX_REFCOUNT);
+        let c = w.upgrade().unwrap();
+        let x = w.clone();
+        assert_eq!(Ark::strong_count(&a), MAX_REFCOUNT + 1);
+        assert_eq!(Ark::weak_count(&a), MAX_REFCOUNT);
+
+        // Leak the references created above, and restore the counts so that `a` can be dropped.
+        std::mem::forget((b, c, w, x));
+        seed_counts(&a, 1);
+    }
+
+    // Runs one of the `overflow_helper_*` tests in a child process, and checks that it aborted.
+    pub(crate) fn assert_aborts(helper: &str) {
+        use std::process::{Command, Stdio};
+
+        let status = Command::new(std::env::current_exe().unwrap())
+            .args(["--ignored", "--exact", "--test-threads=1", helper])
+            .env("ARK_OVERFLOW_HELPER", "1")
+            .stdout(Stdio::null())
+            .stderr(Stdio::null())
+            .status()
+            .unwrap();
+
+        assert!(!status.success());
+
+        #[cfg(unix)]
+        {
+            use std::os::unix::process::ExitStatusExt;
+            assert_eq!(status.signal(), Some(6), "expected SIGABRT");
+        }
+    }
+
+    macro_rules! overflow_helper {
+        ($name:ident, $a:ident => $overflow:expr) => {
+            // Not a real test; only does something when run by assert_aborts().
+            #[test]
+            #[ignore]
+            fn $name() {
+                if std::env::var_os("ARK_OVERFLOW_HELPER").is_none() {
+                    return;
+                }
+                let $a = Ark::new(42);
+                seed_counts(&$a, MAX_REFCOUNT + 1);
+                let _ = $overflow;
+                unreachable!();
+            }
+        };
+    }
+
+    overflow_helper!(overflow_helper_clone, a => a.clone());
+    overflow_helper!(overflow_helper_downgrade, a => Ark::downgrade(&a));
+    overflow_helper!(overflow_helper_weak_clone, a => {
+        // Forge an ArkWeak without touching the (already seeded) counts; ManuallyDrop keeps it
+        // from releasing a reference that it never held.
+        let w = ManuallyDrop::new(ArkWeak {
+            ptr: a.ptr,
+            alloc: Global,
+            layout: unsafe { AllocLayout::of(a.ptr) },
+        });
+        ArkWeak::clone(&w)
+    });
+    overflow_helper!(overflow_helper_upgrade, a => {
+        let w = ManuallyDrop::new(ArkWeak {
+            ptr: a.ptr,
+            alloc: Global,
+            layout: unsafe { AllocLayout::of(a.ptr) },
+        });
+        w.upgrade()
+    });
+
+    #[test]
+    fn clone_aborts_on_refcount_overflow() {
+        assert_aborts("tests::overflow_helper_clone");
+    }
+
+    #[test]
+    fn downgrade_aborts_on_refcount_overflow() {
+        assert_aborts("tests::overflow_helper_downgrade");
+    }
+
+    #[test]
+    fn weak_clone_aborts_on_refcount_overflow() {
+        assert_aborts("tests::overflow_helper_weak_clone");
+    }
+
+    #[test]
+    fn upgrade_aborts_on_refcount_overflow() {
+        assert_aborts("tests::overflow_helper_upgrade");
     }
 
     #[test]
@@ -172,6 +1169,7 @@ mod tests {
     #[allow(unused_mut)]
     fn drop_checker_sees_that_contents_are_dropped() {
         #[derive(Debug)]
+        #[allow(dead_code)]
         struct BadDrop<T: Debug>(T);
 
         impl<T: Debug> Drop for BadDrop<T> {
@@ -0,0 +1,169 @@
+use std::{cell::Cell, mem::ManuallyDrop, ptr::NonNull};
+
+use allocator_api2::alloc::Global;
+
+use crate::{Ark, ArkInner, Shared, SharedWeak};
+
+/// A single-threaded reference counted pointer, like [`Ark`] but with non-atomic counts.
+///
+/// `Rk` uses the same allocation layout as `Ark`, so a uniquely owned `Rk` can be converted into
+/// an `Ark` in place with [`Rk::into_ark`], once it needs to be shared across threads.  Since the
+/// counts are not atomic, `Rk` is neither `Send` nor `Sync`.
+pub type Rk<T, A = Global> = Shared<Cell<usize>, T, A>;
+
+/// A weak reference to the data shared by one or more [`Rk`]s.
+///
+/// See [`SharedWeak`].
+pub type RkWeak<T, A = Global> = SharedWeak<Cell<usize>, T, A>;
+
+impl<T: ?Sized> Rk<T> {
+    /// Converts a uniquely owned `Rk` into an [`Ark`], without reallocating or moving the data.
+    ///
+    /// If there are other `Rk`s or [`RkWeak`]s to the same allocation, the `Rk` is returned back
+    /// in `Err`.
+    pub fn into_ark(this: Self) -> std::result::Result<Ark<T>, Self> {
+        if !this.is_unique() {
+            return Err(this);
+        }
+
+        let this = ManuallyDrop::new(this);
+
+        // Safety: Inner<Cell<usize>, T> and Inner<AtomicUsize, T> have the same layout (asserted
+        // in the inner module), and both counts are one, which is also a valid state for an Ark
+        // with no ArkWeaks.  Since we were the only reference, no one else can observe the
+        // counters changing from non-atomic to atomic.
+        unsafe {
+            let ptr = NonNull::new_unchecked(this.ptr.as_ptr() as *mut ArkInner<T>);
+            Ok(Ark::from_inner(ptr))
+        }
+    }
+}
+
+// Rk shares its implementation with Ark, which is tested in lib.rs and in tests/; only what
+// depends on the counts being non-atomic is tested here.
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::inner::MAX_REFCOUNT;
+
+    #[test]
+    fn is_neither_send_nor_sync() {
+        // Only one of these impls applies to a given type, unless it is Send (or Sync), in which
+        // case `_` below cannot be inferred and this does not compile.
+        trait AmbiguousIfSend<X> {
+            fn check() {}
+        }
+        impl<T: ?Sized> AmbiguousIfSend<()> for T {}
+        impl<T: ?Sized + Send> AmbiguousIfSend<u8> for T {}
+
+        trait AmbiguousIfSync<X> {
+            fn check() {}
+        }
+        impl<T: ?Sized> AmbiguousIfSync<()> for T {}
+        impl<T: ?Sized + Sync> AmbiguousIfSync<u8> for T {}
+
+        <Rk<i32> as AmbiguousIfSend<_>>::check();
+        <Rk<i32> as AmbiguousIfSync<_>>::check();
+        <RkWeak<i32> as AmbiguousIfSend<_>>::check();
+        <RkWeak<i32> as AmbiguousIfSync<_>>::check();
+    }
+
+    // Sets the counts of `a` as if `n - 1` strong (and weak) references had been leaked.
+    fn seed_counts(a: &Rk<i32>, n: usize) {
+        a.inner().strong_count.set(n);
+        a.inner().weak_count.set(n);
+    }
+
+    #[test]
+    fn counts_can_reach_max_refcount() {
+        let a = Rk::new(42);
+
+        seed_counts(&a, MAX_REFCOUNT);
+        let b = a.clone();
+        let w = Rk::downgrade(&a);
+        assert_eq!(Rk::strong_count(&a), MAX_REFCOUNT + 1);
+        assert_eq!(Rk::weak_count(&a), MAX_REFCOUNT);
+
+        seed_counts(&a, MAX_REFCOUNT);
+        let c = w.upgrade().unwrap();
+        let x = w.clone();
+        assert_eq!(Rk::strong_count(&a), MAX_REFCOUNT + 1);
+        assert_eq!(Rk::weak_count(&a), MAX_REFCOUNT);
+
+        // Leak the references created above, and restore the counts so that `a` can be dropped.
+        std::mem::forget((b, c, w, x));
+        seed_counts(&a, 1);
+    }
+
+    // Not real tests; they only do something when run by assert_aborts(), like the ones for Ark.
+    #[test]
+    #[ignore]
+    fn overflow_helper_clone() {
+        if std::env::var_os("ARK_OVERFLOW_HELPER").is_some() {
+            let a = Rk::new(42);
+            seed_counts(&a, MAX_REFCOUNT + 1);
+            let _ = a.clone();
+            unreachable!();
+        }
+    }
+
+    #[test]
+    #[ignore]
+    fn overflow_helper_downgrade() {
+        if std::env::var_os("ARK_OVERFLOW_HELPER").is_some() {
+            let a = Rk::new(42);
+            seed_counts(&a, MAX_REFCOUNT + 1);
+            let _ = Rk::downgrade(&a);
+            unreachable!();
+        }
+    }
+
+    #[test]
+    fn clone_aborts_on_refcount_overflow() {
+        crate::tests::assert_aborts("rk::tests::overflow_helper_clone");
+    }
+
+    #[test]
+    fn downgrade_aborts_on_refcount_overflow() {
+        crate::tests::assert_aborts("rk::tests::overflow_helper_downgrade");
+    }
+
+    #[test]
+    fn unique_access_checks_both_counts() {
+        let mut a = Rk::new(String::from("hi"));
+        let b = a.clone();
+        assert!(Rk::get_mut(&mut a).is_none());
+        drop(b);
+
+        let w = Rk::downgrade(&a);
+        assert!(Rk::get_mut(&mut a).is_none());
+        drop(w);
+
+        Rk::get_mut(&mut a).unwrap().push('!');
+        assert_eq!(Rk::try_unwrap(a).unwrap(), "hi!");
+    }
+
+    #[test]
+    fn converts_into_ark_in_place() {
+        let a: Rk<str> = Rk::from("hi");
+        let b = a.clone();
+        let a = Rk::into_ark(a).unwrap_err();
+        drop(b);
+
+        let w = Rk::downgrade(&a);
+        let a = Rk::into_ark(a).unwrap_err();
+        drop(w);
+
+        let ptr: *const str = &*a;
+        let ark = Rk::into_ark(a).unwrap();
+        assert!(std::ptr::eq(ptr, &*ark));
+
+        let other = ark.clone();
+        std::thread::spawn(move || assert_eq!(&*other, "hi"))
+            .join()
+            .unwrap();
+
+        assert_eq!(Ark::strong_count(&ark), 1);
+        assert_eq!(Ark::weak_count(&ark), 0);
+    }
+}
@@ -0,0 +1,320 @@
+use std::{
+    fmt::{Debug, Formatter, Result},
+    marker::PhantomData,
+    ptr,
+};
+
+use crate::{
+    sync::{fence, hint, AtomicBool, AtomicPtr, Ordering},
+    Ark,
+};
+
+/// A shared pointer to an [`Ark`] that can be atomically replaced.
+///
+/// Readers get the current value with [`ArkSwap::load`], which is lock-free, while writers replace
+/// it with [`ArkSwap::store`], [`ArkSwap::swap`] or [`ArkSwap::compare_and_swap`].
+///
+/// Loading must increment the strong count of the current `Ark`, but that `Ark` could be
+/// concurrently replaced and its last reference dropped.  To prevent this, each reader first
+/// publishes the pointer it is about to increment in a *hazard* slot, and writers wait for the
+/// (short) time until no hazard slot holds the pointer they replaced before releasing it.
+pub struct ArkSwap<T> {
+    // Owns one strong reference, from Ark::into_raw().
+    current: AtomicPtr<T>,
+
+    // Singly linked list of hazard slots, which are only freed when the ArkSwap is dropped.
+    slots: AtomicPtr<Slot<T>>,
+
+    // ArkSwap<T> behaves like (and owns) an Ark<T>, including for auto traits.
+    _phantom: PhantomData<Ark<T>>,
+}
+
+struct Slot<T> {
+    hazard: AtomicPtr<T>,
+    in_use: AtomicBool,
+
+    // Immutable after the slot is published in the list.
+    next: *mut Slot<T>,
+}
+
+impl<T> ArkSwap<T> {
+    pub fn new(value: Ark<T>) -> ArkSwap<T> {
+        ArkSwap {
+            current: AtomicPtr::new(Ark::into_raw(value) as *mut T),
+            slots: AtomicPtr::new(ptr::null_mut()),
+            _phantom: PhantomData,
+        }
+    }
+
+    /// Returns (a clone of) the current `Ark`.
+    pub fn load(&self) -> Ark<T> {
+        let slot = self.acquire_slot();
+
+        // Ordering: Acquire synchronizes with the Release half of the writer's replacement, so
+        // that the data (as initialized by the writer) is visible.
+        let mut ptr = self.current.load(Ordering::Acquire);
+        while let Err(again) = self.protect(slot, ptr) {
+            ptr = again;
+        }
+
+        // Safety: the pointer is protected by the hazard.
+        let ark = unsafe { Self::clone_protected(ptr) };
+
+        Self::release_slot(slot);
+        ark
+    }
+
+    /// Replaces the current `Ark`, dropping the previous one.
+    pub fn store(&self, value: Ark<T>) {
+        drop(self.swap(value));
+    }
+
+    /// Replaces the current `Ark`, returning the previous one.
+    pub fn swap(&self, value: Ark<T>) -> Ark<T> {
+        let new = Ark::into_raw(value) as *mut T;
+
+        // Ordering: see load().
+        let old = self.current.swap(new, Ordering::AcqRel);
+
+        // Safety: `old` was current until the swap above, so we now own its strong reference.
+        unsafe { self.release_old(old) }
+    }
+
+    /// Replaces the current `Ark` with `new`, but only if it points to the same allocation as
+    /// `current` (see [`Ark::ptr_eq`]).
+    ///
+    /// On success, returns the replaced value.  Otherwise, returns the value that was found
+    /// instead of `current`, together with `new`, which is handed back to the caller.
+    pub fn compare_and_swap(
+        &self,
+        current: &Ark<T>,
+        new: Ark<T>,
+    ) -> std::result::Result<Ark<T>, (Ark<T>, Ark<T>)> {
+        let expected = Ark::as_ptr(current) as *mut T;
+        let new_ptr = Ark::into_raw(new) as *mut T;
+
+        // Only needed (and acquired) if the exchange fails.
+        let mut slot = None;
+
+        let result = loop {
+            // Ordering: see swap() and load().
+            match self.current.compare_exchange(
+                expected,
+                new_ptr,
+                Ordering::AcqRel,
+                Ordering::Relaxed,
+            ) {
+                Ok(old) => break Ok(old),
+                Err(actual) => {
+                    // The value that was found must be protected before its strong count can be
+                    // incremented; if it has already been replaced, it may be gone, and the
+                    // exchange must be retried against the new value (which may be `expected`).
+                    let slot = *slot.get_or_insert_with(|| self.acquire_slot());
+                    if self.protect(slot, actual).is_ok() {
+                        // Safety: the pointer is protected by the hazard.
+                        break Err(unsafe { Self::clone_protected(actual) });
+                    }
+                }
+            }
+        };
+
+        // The hazard must be cleared before release_old(), which could otherwise wait on it.
+        if let Some(slot) = slot {
+            Self::release_slot(slot);
+        }
+
+        match result {
+            // Safety: see swap().
+            Ok(old) => Ok(unsafe { self.release_old(old) }),
+
+            // Safety: new_ptr came from Ark::into_raw() just above, and was never published.
+            Err(actual) => Err((actual, unsafe { Ark::from_raw(new_ptr) })),
+        }
+    }
+
+    /// Consumes the `ArkSwap`, returning the current `Ark`.
+    pub fn into_inner(self) -> Ark<T> {
+        // Exclusive access: there can be no concurrent readers.
+        let ptr = self.current.swap(ptr::null_mut(), Ordering::Relaxed);
+
+        // Safety: we owned the strong reference, which is now transferred to the returned Ark
+        // (and the null pointer left behind is ignored when dropping self).
+        unsafe { Ark::from_raw(ptr) }
+    }
+
+    /// # Safety
+    ///
+    /// `old` must have been replaced in `self.current` by the caller, who now owns its strong
+    /// reference.
+    unsafe fn release_old(&self, old: *mut T) -> Ark<T> {
+        // Wait for any reader that may be about to increment the strong count of `old`.  New
+        // readers will not get to increment it, because they will see the new value when they
+        // validate their hazard; so this cannot wait indefinitely.
+        //
+        // Ordering: see load(); the fence also ensures that we see every slot that a reader may
+        // have published its hazard in.  Acquire on the hazard loads synchronizes with the Release
+        // store that clears them, so that the readers' increments happen before our release.
+        fence(Ordering::SeqCst);
+
+        let mut cur = self.slots.load(Ordering::Acquire);
+        while !cur.is_null() {
+            let slot = &*cur;
+
+            while slot.hazard.load(Ordering::Acquire) == old {
+                hint::spin_loop();
+            }
+
+            cur = slot.next;
+        }
+
+        Ark::from_raw(old)
+    }
+
+    /// Publishes `ptr` as the hazard of `slot`, and checks that it is still current; if it is
+    /// not, returns the new value, which is not protected.
+    fn protect(&self, slot: &Slot<T>, ptr: *mut T) -> std::result::Result<(), *mut T> {
+        slot.hazard.store(ptr, Ordering::Relaxed);
+
+        // Ordering: the SeqCst fence between publishing the hazard and reloading `current` pairs
+        // with the one in release_old(), between replacing `current` and scanning the hazards:
+        // either our fence comes first, and the writer sees our hazard (and our slot), or the
+        // writer's fence comes first, and we see the replaced value when reloading.  Acquire on
+        // the reload makes the data visible (see load()).
+        fence(Ordering::SeqCst);
+
+        let again = self.current.load(Ordering::Acquire);
+        if again == ptr {
+            Ok(())
+        } else {
+            Err(again)
+        }
+    }
+
+    /// # Safety
+    ///
+    /// `ptr` must be protected by a hazard: it must have been current after being published in
+    /// a slot (see protect()), and the hazard must not have been cleared yet.
+    unsafe fn clone_protected(ptr: *mut T) -> Ark<T> {
+        // The pointer came from Ark::into_raw(), and the allocation is still alive: the strong
+        // reference owned by the ArkSwap will not be released until the hazard is cleared.
+        Ark::increment_strong_count(ptr);
+        Ark::from_raw(ptr)
+    }
+
+    fn release_slot(slot: &Slot<T>) {
+        // Ordering: Release so that the writer waiting for this hazard to clear happens-after our
+        // increment.
+        slot.hazard.store(ptr::null_mut(), Ordering::Release);
+        slot.in_use.store(false, Ordering::Release);
+    }
+
+    fn acquire_slot(&self) -> &Slot<T> {
+        // Ordering: Acquire synchronizes with the Release when pushing the slot, making `next`
+        // visible, and with the Release when a slot is given up by another reader.
+        let head = self.slots.load(Ordering::Acquire);
+
+        let mut cur = head;
+        while !cur.is_null() {
+            // Safety: slots are never freed while there are references to the ArkSwap.
+            let slot = unsafe { &*cur };
+
+            if !slot.in_use.load(Ordering::Relaxed)
+                && slot
+                    .in_use
+                    .compare_exchange(false, true, Ordering::Acquire, Ordering::Relaxed)
+                    .is_ok()
+            {
+                return slot;
+            }
+
+            cur = slot.next;
+        }
+
+        // All slots are in use, so add a new one.
+        let slot = Box::into_raw(Box::new(Slot {
+            hazard: AtomicPtr::new(ptr::null_mut()),
+            in_use: AtomicBool::new(true),
+            next: head,
+        }));
+
+        let mut head = head;
+        loop {
+            // Safety: the slot has not been published yet, so we have exclusive access to it.
+            unsafe { (*slot).next = head };
+
+            // Ordering: Release publishes the initialized slot.
+            match self
+                .slots
+                .compare_exchange_weak(head, slot, Ordering::Release, Ordering::Acquire)
+            {
+                // Safety: see above.
+                Ok(_) => return unsafe { &*slot },
+                Err(new_head) => head = new_head,
+            }
+        }
+    }
+}
+
+impl<T> Drop for ArkSwap<T> {
+    fn drop(&mut self) {
+        // Exclusive access: there can be no concurrent readers or writers.
+        let ptr = self.current.swap(ptr::null_mut(), Ordering::Relaxed);
+        if !ptr.is_null() {
+            // Safety: we own the strong reference to the current value.
+            drop(unsafe { Ark::from_raw(ptr) });
+        }
+
+        let mut cur = self.slots.swap(ptr::null_mut(), Ordering::Relaxed);
+        while !cur.is_null() {
+            // Safety: slots were created with Box::into_raw(), and are no longer in use.
+            let slot = unsafe { Box::from_raw(cur) };
+            cur = slot.next;
+        }
+    }
+}
+
+impl<T> From<Ark<T>> for ArkSwap<T> {
+    fn from(value: Ark<T>) -> ArkSwap<T> {
+        ArkSwap::new(value)
+    }
+}
+
+impl<T: Debug> Debug for ArkSwap<T> {
+    fn fmt(&self, f: &mut Formatter<'_>) -> Result {
+        f.debug_tuple("ArkSwap").field(&self.load()).finish()
+    }
+}
+
+#[cfg(all(test, not(feature = "loom")))]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn slots_are_reused() {
+        let swap = ArkSwap::new(Ark::new(42));
+
+        for _ in 0..10 {
+            assert_eq!(*swap.load(), 42);
+        }
+
+        let slot = swap.slots.load(Ordering::Relaxed);
+        assert!(!slot.is_null());
+        assert!(unsafe { (*slot).next.is_null() });
+    }
+
+    #[test]
+    fn holds_exactly_one_strong_reference() {
+        let a = Ark::new(42);
+        let swap = ArkSwap::new(a.clone());
+        assert_eq!(Ark::strong_count(&a), 2);
+
+        let b = swap.load();
+        assert_eq!(Ark::strong_count(&a), 3);
+        drop(b);
+
+        swap.store(Ark::new(43));
+        assert_eq!(Ark::strong_count(&a), 1);
+
+        drop(swap);
+    }
+}
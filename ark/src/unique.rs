@@ -0,0 +1,168 @@
+use std::{
+    fmt::{Debug, Formatter, Result},
+    marker::PhantomData,
+    mem::{ManuallyDrop, MaybeUninit},
+    ops::{Deref, DerefMut},
+    ptr::{self, NonNull},
+};
+
+use allocator_api2::alloc::Global;
+
+use crate::{
+    inner::{self, AllocLayout, Counter, Inner},
+    Ark, ArkInner, ArkWeak,
+};
+
+/// A uniquely owned [`Ark`], whose data can be mutated before it is shared.
+///
+/// Unlike a `Box<T>` that is later converted into an `Ark<T>`, the data is allocated in its final
+/// place from the start, and [`ArkWeak`]s to it can already be created (e.g. to build cyclic
+/// structures); they can only be upgraded after [`UniqueArk::into_ark`] is called.
+pub struct UniqueArk<T: ?Sized> {
+    // The allocation is shared with future Arks and (possibly existing) ArkWeaks, so it has the
+    // same layout; but its strong_count is zero until it is converted into an Ark, which is what
+    // prevents the ArkWeaks from upgrading.  The design is otherwise the same as in Ark.
+    ptr: NonNull<ArkInner<T>>,
+    _phantom: PhantomData<ArkInner<T>>,
+}
+
+impl<T> UniqueArk<T> {
+    pub fn new(data: T) -> UniqueArk<T> {
+        // Safety: the allocation was just created.
+        unsafe { UniqueArk::from_inner(Inner::new(data)) }
+    }
+
+    /// Constructs a new `UniqueArk` with uninitialized data, which can then be initialized in
+    /// place.
+    ///
+    /// See [`Ark::new_uninit`].
+    pub fn new_uninit() -> UniqueArk<MaybeUninit<T>> {
+        // Safety: see new().
+        unsafe { UniqueArk::from_inner(inner::new_uninit()) }
+    }
+}
+
+impl<T> UniqueArk<MaybeUninit<T>> {
+    /// Converts to `UniqueArk<T>`.
+    ///
+    /// # Safety
+    ///
+    /// The data must have been initialized.
+    pub unsafe fn assume_init(self) -> UniqueArk<T> {
+        let this = ManuallyDrop::new(self);
+
+        // MaybeUninit<T> has the same layout as T.
+        UniqueArk {
+            ptr: this.ptr.cast(),
+            _phantom: PhantomData,
+        }
+    }
+}
+
+impl<T: ?Sized> UniqueArk<T> {
+    /// # Safety
+    ///
+    /// `inner` must have been allocated by one of the functions in the `inner` module, with both
+    /// counts still set to one, and no other references to it.
+    unsafe fn from_inner(inner: NonNull<ArkInner<T>>) -> UniqueArk<T> {
+        // There is no Ark yet; the weak reference collectively held by the strong references is
+        // held by us instead.
+        inner.as_ref().strong_count.set(0);
+
+        UniqueArk {
+            ptr: inner,
+            _phantom: PhantomData,
+        }
+    }
+
+    /// Creates a new [`ArkWeak`] pointer to this allocation, which can only be upgraded after
+    /// this is converted into an `Ark`.
+    pub fn downgrade(this: &Self) -> ArkWeak<T> {
+        // Same as in ArkWeak::clone(); weak_count cannot be locked, since that requires an Ark.
+        unsafe { this.ptr.as_ref() }.weak_count.increment();
+
+        ArkWeak {
+            ptr: this.ptr,
+            alloc: Global,
+            // Safety: the data is alive while we exist.
+            layout: unsafe { AllocLayout::of(this.ptr) },
+        }
+    }
+
+    /// Converts into an `Ark`, allowing any `ArkWeak`s to be upgraded from now on.
+    pub fn into_ark(this: Self) -> Ark<T> {
+        let this = ManuallyDrop::new(this);
+
+        // Ordering: set() synchronizes with the increment in ArkWeak::upgrade(), so that an
+        // ArkWeak upgraded on another thread sees all of our writes to the data.
+        unsafe { this.ptr.as_ref() }.strong_count.set(1);
+
+        // Safety: strong_count is now one, and our weak reference is now the one collectively
+        // held by the strong references.
+        unsafe { Ark::from_inner(this.ptr) }
+    }
+}
+
+impl<T: ?Sized> Deref for UniqueArk<T> {
+    type Target = T;
+
+    fn deref(&self) -> &T {
+        // Safety: the allocation is alive and initialized while we exist, and ArkWeaks cannot
+        // access the data until we are converted into an Ark.
+        unsafe { &(*self.ptr.as_ptr()).data }
+    }
+}
+
+impl<T: ?Sized> DerefMut for UniqueArk<T> {
+    fn deref_mut(&mut self) -> &mut T {
+        // Safety: see deref(); we are also the only ones that can access the data.
+        unsafe { &mut (*self.ptr.as_ptr()).data }
+    }
+}
+
+#[cfg(feature = "nightly")]
+unsafe impl<#[may_dangle] T: ?Sized> Drop for UniqueArk<T> {
+    fn drop(&mut self) {
+        drop_impl(self);
+    }
+}
+#[cfg(not(feature = "nightly"))]
+impl<T: ?Sized> Drop for UniqueArk<T> {
+    fn drop(&mut self) {
+        drop_impl(self);
+    }
+}
+
+fn drop_impl<T: ?Sized>(this: &mut UniqueArk<T>) {
+    // Safety: we are the only ones that can access the data, and the layout of the allocation is
+    // computed before it is gone.
+    let layout = unsafe {
+        let layout = AllocLayout::of(this.ptr);
+        ptr::drop_in_place(&mut (*this.ptr.as_ptr()).data);
+        layout
+    };
+
+    // Release our weak reference, possibly deallocating if there are no ArkWeaks left.
+    drop(ArkWeak {
+        ptr: this.ptr,
+        alloc: Global,
+        layout,
+    });
+}
+
+impl<T: ?Sized> From<UniqueArk<T>> for Ark<T> {
+    fn from(unique: UniqueArk<T>) -> Ark<T> {
+        UniqueArk::into_ark(unique)
+    }
+}
+
+impl<T: Debug + ?Sized> Debug for UniqueArk<T> {
+    fn fmt(&self, f: &mut Formatter<'_>) -> Result {
+        Debug::fmt(&**self, f)
+    }
+}
+
+// ArkWeaks created from a UniqueArk<T> can later be upgraded into Ark<T>s, so the bounds must be
+// the same as Ark<T>'s.
+unsafe impl<T: Send + Sync + ?Sized> Send for UniqueArk<T> {}
+unsafe impl<T: Send + Sync + ?Sized> Sync for UniqueArk<T> {}
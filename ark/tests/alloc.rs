@@ -0,0 +1,262 @@
+// Tests for Arks in custom allocators, with an allocator that checks that every allocation it
+// makes is freed exactly once (and with the same layout).
+#![cfg(not(feature = "loom"))]
+#![cfg_attr(feature = "nightly", feature(allocator_api))]
+
+use allocator_api2::alloc::{AllocError, Allocator, Global, Layout};
+use ark::{Ark, ArkWeak};
+use std::{
+    collections::HashMap,
+    panic::{self, AssertUnwindSafe},
+    ptr::NonNull,
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        Arc, Mutex,
+    },
+    thread,
+};
+
+#[derive(Default)]
+struct Stats {
+    allocs: usize,
+    deallocs: usize,
+    live: HashMap<usize, Layout>,
+}
+
+// Clones share the same stats, like clones of an Ark's allocator share its allocations.
+#[derive(Clone, Default)]
+struct Counting(Arc<Mutex<Stats>>);
+
+impl Counting {
+    fn allocs(&self) -> usize {
+        self.0.lock().unwrap().allocs
+    }
+
+    fn deallocs(&self) -> usize {
+        self.0.lock().unwrap().deallocs
+    }
+
+    fn assert_all_freed(&self) {
+        let stats = self.0.lock().unwrap();
+        assert_eq!(stats.allocs, stats.deallocs);
+        assert!(stats.live.is_empty());
+    }
+}
+
+unsafe impl Allocator for Counting {
+    fn allocate(&self, layout: Layout) -> Result<NonNull<[u8]>, AllocError> {
+        let mem = Global.allocate(layout)?;
+
+        let mut stats = self.0.lock().unwrap();
+        stats.allocs += 1;
+        let prev = stats
+            .live
+            .insert(mem.cast::<u8>().as_ptr() as usize, layout);
+        assert!(prev.is_none(), "address handed out twice");
+
+        Ok(mem)
+    }
+
+    unsafe fn deallocate(&self, ptr: NonNull<u8>, layout: Layout) {
+        {
+            let mut stats = self.0.lock().unwrap();
+            stats.deallocs += 1;
+            let allocated = stats.live.remove(&(ptr.as_ptr() as usize));
+            assert_eq!(allocated, Some(layout), "double free or wrong layout");
+        }
+
+        Global.deallocate(ptr, layout);
+    }
+}
+
+#[test]
+fn new_in_allocates_and_frees_once() {
+    let alloc = Counting::default();
+
+    let a = Ark::new_in(String::from("hi"), alloc.clone());
+    let b = Ark::clone(&a);
+    assert_eq!(alloc.allocs(), 1);
+    assert_eq!(*b, "hi");
+
+    drop(a);
+    assert_eq!(alloc.deallocs(), 0);
+    drop(b);
+    alloc.assert_all_freed();
+}
+
+#[test]
+fn weak_pointers_keep_the_allocation_alive() {
+    let alloc = Counting::default();
+
+    let a = Ark::new_in(42, alloc.clone());
+    let w: ArkWeak<i32, Counting> = Ark::downgrade(&a);
+    let w2 = w.clone();
+
+    drop(a);
+    assert!(w.upgrade().is_none());
+    assert_eq!(alloc.deallocs(), 0);
+
+    drop(w);
+    drop(w2);
+    alloc.assert_all_freed();
+}
+
+#[test]
+fn unwrapping_frees_the_allocation() {
+    let alloc = Counting::default();
+
+    let a = Ark::new_in(vec![1, 2], alloc.clone());
+    let b = Ark::clone(&a);
+    let a = Ark::try_unwrap(a).unwrap_err();
+    drop(b);
+    assert_eq!(Ark::try_unwrap(a).unwrap(), [1, 2]);
+
+    let a = Ark::new_in(3, alloc.clone());
+    let b = Ark::clone(&a);
+    assert_eq!(Ark::into_inner(a), None);
+    assert_eq!(Ark::into_inner(b), Some(3));
+
+    assert_eq!(alloc.allocs(), 2);
+    alloc.assert_all_freed();
+}
+
+#[test]
+fn make_mut_allocates_in_the_same_allocator() {
+    let alloc = Counting::default();
+
+    // Cloning into a new allocation.
+    let mut a = Ark::new_in(1, alloc.clone());
+    let b = Ark::clone(&a);
+    *Ark::make_mut(&mut a) += 1;
+    assert_eq!((*a, *b), (2, 1));
+    assert_eq!(alloc.allocs(), 2);
+
+    // Moving the data out of an allocation that only has weak pointers left.
+    drop(b);
+    let w = Ark::downgrade(&a);
+    *Ark::make_mut(&mut a) += 1;
+    assert!(w.upgrade().is_none());
+    assert_eq!(alloc.allocs(), 3);
+
+    drop(w);
+    drop(a);
+    alloc.assert_all_freed();
+}
+
+// Like Counting, but clones panic once `armed` is set.
+#[derive(Default)]
+struct PanicOnClone {
+    alloc: Counting,
+    armed: Arc<AtomicBool>,
+}
+
+impl Clone for PanicOnClone {
+    fn clone(&self) -> Self {
+        assert!(!self.armed.load(Ordering::Relaxed), "clone panicked");
+
+        PanicOnClone {
+            alloc: self.alloc.clone(),
+            armed: Arc::clone(&self.armed),
+        }
+    }
+}
+
+unsafe impl Allocator for PanicOnClone {
+    fn allocate(&self, layout: Layout) -> Result<NonNull<[u8]>, AllocError> {
+        self.alloc.allocate(layout)
+    }
+
+    unsafe fn deallocate(&self, ptr: NonNull<u8>, layout: Layout) {
+        self.alloc.deallocate(ptr, layout)
+    }
+}
+
+#[test]
+fn make_mut_survives_a_panicking_allocator_clone() {
+    let alloc = PanicOnClone::default();
+    let data = Arc::new(());
+
+    let mut a = Ark::new_in(Arc::clone(&data), alloc.clone());
+    let w = Ark::downgrade(&a);
+
+    // Only weak pointers are left, so make_mut() would move the data into a new allocation, which
+    // needs a clone of the allocator.
+    alloc.armed.store(true, Ordering::Relaxed);
+    let result = panic::catch_unwind(AssertUnwindSafe(|| {
+        Ark::make_mut(&mut a);
+    }));
+    assert!(result.is_err());
+    alloc.armed.store(false, Ordering::Relaxed);
+
+    // Nothing was moved out or released.
+    assert_eq!(Ark::strong_count(&a), 1);
+    assert_eq!(Ark::weak_count(&a), 1);
+    assert!(w.upgrade().is_some());
+    assert_eq!(Arc::strong_count(&data), 2);
+
+    Ark::make_mut(&mut a);
+    assert!(w.upgrade().is_none());
+
+    drop(w);
+    drop(a);
+    assert_eq!(Arc::strong_count(&data), 1);
+    alloc.alloc.assert_all_freed();
+}
+
+#[test]
+fn data_is_dropped_before_being_freed() {
+    let alloc = Counting::default();
+    let data = Arc::new(());
+
+    let a = Ark::new_in(Arc::clone(&data), alloc.clone());
+    let w = Ark::downgrade(&a);
+    drop(a);
+    assert_eq!(Arc::strong_count(&data), 1);
+    assert_eq!(alloc.deallocs(), 0);
+
+    drop(w);
+    alloc.assert_all_freed();
+}
+
+#[test]
+fn can_be_shared_across_threads() {
+    let alloc = Counting::default();
+
+    for _ in 0..10 {
+        let a = Ark::new_in(42, alloc.clone());
+
+        let handles: Vec<_> = (0..4)
+            .map(|_| {
+                let a = Ark::clone(&a);
+                let w = Ark::downgrade(&a);
+                thread::spawn(move || {
+                    assert_eq!(*a, 42);
+                    drop(a);
+                    drop(w.upgrade());
+                })
+            })
+            .collect();
+
+        drop(a);
+        for handle in handles {
+            handle.join().unwrap();
+        }
+    }
+
+    assert_eq!(alloc.allocs(), 10);
+    alloc.assert_all_freed();
+}
+
+#[test]
+#[cfg(feature = "nightly")]
+fn can_hold_a_trait_object() {
+    use std::fmt::Debug;
+
+    let alloc = Counting::default();
+
+    let a: Ark<dyn Debug, Counting> = Ark::new_in(42, alloc.clone());
+    assert_eq!(format!("{:?}", a), "42");
+
+    drop(a);
+    alloc.assert_all_freed();
+}
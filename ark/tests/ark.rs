@@ -1,5 +1,12 @@
-use ark::Ark;
-use std::thread;
+// Loom atomics must be accessed inside of a loom model, so the regular tests are disabled when the
+// `loom` feature is enabled; run the model checking tests with `--features loom --test loom`.
+#![cfg(not(feature = "loom"))]
+
+use ark::{Ark, ArkWeak, UniqueArk};
+use std::{
+    sync::{Arc, Barrier},
+    thread,
+};
 
 #[test]
 fn smoke_test() {
@@ -32,3 +39,287 @@ fn smoke_test_where_ark_is_sync() {
 
     handle.join().unwrap();
 }
+
+#[test]
+fn weak_upgrades_race_with_last_drop() {
+    for _ in 0..1000 {
+        let a = Ark::new(String::from("hi"));
+        let w = Ark::downgrade(&a);
+
+        let handle = thread::spawn(move || {
+            if let Some(b) = w.upgrade() {
+                assert_eq!(*b, "hi");
+            }
+        });
+
+        drop(a);
+
+        handle.join().unwrap();
+    }
+}
+
+#[test]
+fn get_mut_requires_a_unique_reference() {
+    let mut a = Ark::new(42);
+
+    *Ark::get_mut(&mut a).unwrap() += 1;
+    assert_eq!(*a, 43);
+
+    let b = Ark::clone(&a);
+    assert!(Ark::get_mut(&mut a).is_none());
+    drop(b);
+
+    let w = Ark::downgrade(&a);
+    assert!(Ark::get_mut(&mut a).is_none());
+    drop(w);
+
+    assert!(Ark::get_mut(&mut a).is_some());
+}
+
+#[test]
+fn make_mut_clones_only_when_shared() {
+    let mut a = Ark::new(String::from("hi"));
+    let b = Ark::clone(&a);
+
+    Ark::make_mut(&mut a).push('!');
+    assert_eq!(*a, "hi!");
+    assert_eq!(*b, "hi");
+
+    // Now unique: must not reallocate.
+    let before: *const String = &*a;
+    Ark::make_mut(&mut a).push('!');
+    assert_eq!(before, &*a as *const String);
+    assert_eq!(*a, "hi!!");
+
+    // Only weak references left: the data is moved to a new allocation.
+    let w = Ark::downgrade(&a);
+    Ark::make_mut(&mut a).push('!');
+    assert_eq!(*a, "hi!!!");
+    assert!(w.upgrade().is_none());
+    assert_eq!(Ark::weak_count(&a), 0);
+}
+
+#[test]
+fn try_unwrap_returns_the_ark_when_shared() {
+    let a = Ark::new(String::from("hi"));
+    let b = Ark::clone(&a);
+
+    let a = Ark::try_unwrap(a).unwrap_err();
+    drop(b);
+
+    let w = Ark::downgrade(&a);
+    assert_eq!(Ark::try_unwrap(a).unwrap(), "hi");
+    assert!(w.upgrade().is_none());
+}
+
+#[test]
+fn into_inner_races_yield_exactly_one_value() {
+    const THREADS: usize = 8;
+
+    for _ in 0..100 {
+        let a = Ark::new(String::from("hi"));
+        let barrier = Arc::new(Barrier::new(THREADS));
+
+        let mut arks = vec![a.clone(); THREADS - 1];
+        arks.push(a);
+
+        let handles: Vec<_> = arks
+            .into_iter()
+            .map(|a| {
+                let barrier = Arc::clone(&barrier);
+                thread::spawn(move || {
+                    barrier.wait();
+                    Ark::into_inner(a)
+                })
+            })
+            .collect();
+
+        let values: Vec<_> = handles
+            .into_iter()
+            .filter_map(|h| h.join().unwrap())
+            .collect();
+
+        assert_eq!(values, ["hi"]);
+    }
+}
+
+#[test]
+fn make_mut_races_do_not_affect_other_clones() {
+    const THREADS: usize = 8;
+
+    for _ in 0..100 {
+        let a = Ark::new(vec![0]);
+        let barrier = Arc::new(Barrier::new(THREADS));
+
+        let handles: Vec<_> = (0..THREADS)
+            .map(|i| {
+                let mut a = Ark::clone(&a);
+                let barrier = Arc::clone(&barrier);
+                thread::spawn(move || {
+                    barrier.wait();
+                    Ark::make_mut(&mut a).push(i);
+                    assert_eq!(*a, [0, i]);
+                })
+            })
+            .collect();
+
+        for h in handles {
+            h.join().unwrap();
+        }
+
+        assert_eq!(*a, [0]);
+        assert_eq!(Ark::strong_count(&a), 1);
+    }
+}
+
+#[test]
+fn get_mut_races_with_downgrade_and_upgrade() {
+    for _ in 0..1000 {
+        let mut a = Ark::new(0);
+        let w = Ark::downgrade(&a);
+
+        let handle = thread::spawn(move || {
+            // Upgrade, downgrade and drop the upgraded Ark: at no point can a observe itself as
+            // unique while this is happening.
+            if let Some(b) = w.upgrade() {
+                let w2 = Ark::downgrade(&b);
+                drop(b);
+                drop(w2);
+            }
+        });
+
+        while Ark::get_mut(&mut a).is_none() {
+            thread::yield_now();
+        }
+        *Ark::get_mut(&mut a).unwrap() += 1;
+
+        handle.join().unwrap();
+        assert_eq!(*a, 1);
+    }
+}
+
+#[test]
+fn forwards_comparisons_and_hashing_to_the_data() {
+    use std::collections::{BTreeSet, HashMap};
+
+    let mut map: HashMap<Ark<str>, i32> = HashMap::new();
+    map.insert(Ark::from("one"), 1);
+    map.insert(Ark::from("two"), 2);
+    assert_eq!(map.get("two"), Some(&2));
+    assert_eq!(map[&Ark::from("one")], 1);
+
+    let set: BTreeSet<Ark<i32>> = [3, 1, 2].iter().copied().map(Ark::new).collect();
+    let sorted: Vec<i32> = set.iter().map(|x| **x).collect();
+    assert_eq!(sorted, [1, 2, 3]);
+
+    assert!(Ark::new(1) < Ark::new(2));
+    assert_eq!(Ark::new(1), Ark::new(1));
+}
+
+#[test]
+fn forwards_formatting_to_the_data() {
+    let a: Ark<str> = Ark::from("hi");
+    assert_eq!(format!("{} {:?}", a, a), "hi \"hi\"");
+    assert_eq!(format!("{:p}", a), format!("{:p}", Ark::as_ptr(&a)));
+}
+
+#[test]
+fn implements_conversions_and_defaults() {
+    let a: Ark<[i32]> = (1..=3).collect();
+    assert_eq!(&*a, [1, 2, 3]);
+
+    let b: Ark<String> = String::from("hi").into();
+    let s: &String = b.as_ref();
+    assert_eq!(s, "hi");
+
+    assert_eq!(*Ark::<i32>::default(), 0);
+    assert_eq!(&*Ark::<str>::default(), "");
+    assert!(Ark::<[i32]>::default().is_empty());
+}
+
+#[test]
+fn can_be_pinned() {
+    use std::{marker::PhantomPinned, pin::Pin};
+
+    fn assert_unpin<T: Unpin>() {}
+    assert_unpin::<Ark<PhantomPinned>>();
+
+    let p: Pin<Ark<PhantomPinned>> = Ark::pin(PhantomPinned);
+    let _ = p.clone();
+
+    let u: Pin<Ark<str>> = Ark::into_pin(Ark::from("hi"));
+    assert_eq!(&*u, "hi");
+}
+
+#[test]
+fn new_cyclic_gives_the_data_a_weak_reference_to_itself() {
+    struct Node {
+        me: ArkWeak<Node>,
+        value: i32,
+    }
+
+    let node = Ark::new_cyclic(|me| {
+        assert!(me.upgrade().is_none());
+        Node {
+            me: me.clone(),
+            value: 42,
+        }
+    });
+
+    let me = node.me.upgrade().unwrap();
+    assert!(Ark::ptr_eq(&me, &node));
+    assert_eq!(me.value, 42);
+    assert_eq!(Ark::weak_count(&node), 1);
+}
+
+#[test]
+fn new_cyclic_cleans_up_after_a_panic() {
+    use std::panic::{self, AssertUnwindSafe};
+
+    let mut stash = None;
+    let result = panic::catch_unwind(AssertUnwindSafe(|| {
+        Ark::<i32>::new_cyclic(|me| {
+            stash = Some(me.clone());
+            panic!("oops");
+        })
+    }));
+
+    assert!(result.is_err());
+    assert!(stash.unwrap().upgrade().is_none());
+}
+
+#[test]
+fn unique_ark_can_be_mutated_before_being_shared() {
+    let mut unique = UniqueArk::new(vec![1]);
+    let weak = UniqueArk::downgrade(&unique);
+
+    unique.push(2);
+    assert!(weak.upgrade().is_none());
+
+    let a = UniqueArk::into_ark(unique);
+    assert_eq!(*weak.upgrade().unwrap(), [1, 2]);
+    assert_eq!(*a, [1, 2]);
+}
+
+#[test]
+fn new_uninit_builds_large_values_in_place() {
+    // Larger than the default stack of a test thread, so this would overflow it if the value were
+    // ever built (or copied) on the stack.
+    const SIZE: usize = 16 << 20;
+
+    let mut a = Ark::<[u8; SIZE]>::new_uninit();
+    let data = Ark::get_mut(&mut a).unwrap();
+
+    // Safety: MaybeUninit<[u8; N]> has the same layout as [u8; N].
+    unsafe { std::ptr::write_bytes(data.as_mut_ptr(), 42, 1) };
+
+    let a = unsafe { a.assume_init() };
+    assert!(a.iter().all(|&x| x == 42));
+
+    let mut s = Ark::<String>::new_uninit_slice(2);
+    for (i, x) in Ark::get_mut(&mut s).unwrap().iter_mut().enumerate() {
+        x.write(i.to_string());
+    }
+    let s = unsafe { s.assume_init() };
+    assert_eq!(&*s, ["0", "1"]);
+}
@@ -0,0 +1,261 @@
+// Model checking of the atomic orderings used by Ark and ArkWeak.  Run with:
+//
+//     cargo test --release --features loom --test loom
+//
+// Loom only tracks accesses to its own cells, so the shared data uses a loom UnsafeCell that is
+// read while the Arks are alive and written to when dropped; if the orderings on the last drop
+// (or on any other path that ends up dropping or mutating the data) were too weak, those writes
+// would not happen-after the reads, and loom would report a data race.
+
+#![cfg(feature = "loom")]
+
+use ark::Ark;
+use loom::{cell::UnsafeCell, thread};
+
+struct Data(UnsafeCell<usize>);
+
+// Safety: all mutable accesses to the cell go through &mut Data (or drop), which Ark only allows
+// when there are no other references; that is exactly what is being checked.
+unsafe impl Sync for Data {}
+
+impl Data {
+    fn new(value: usize) -> Data {
+        Data(UnsafeCell::new(value))
+    }
+
+    fn get(&self) -> usize {
+        self.0.with(|ptr| unsafe { *ptr })
+    }
+
+    fn set(&mut self, value: usize) {
+        self.0.with_mut(|ptr| unsafe { *ptr = value })
+    }
+}
+
+impl Clone for Data {
+    fn clone(&self) -> Data {
+        Data::new(self.get())
+    }
+}
+
+impl Drop for Data {
+    fn drop(&mut self) {
+        self.set(0);
+    }
+}
+
+#[test]
+fn concurrent_clones_and_drops() {
+    loom::model(|| {
+        let a = Ark::new(Data::new(42));
+        let b = Ark::clone(&a);
+
+        let handle = thread::spawn(move || {
+            let c = Ark::clone(&b);
+            assert_eq!(c.get(), 42);
+            drop(b);
+            assert_eq!(c.get(), 42);
+        });
+
+        let d = Ark::clone(&a);
+        drop(a);
+        assert_eq!(d.get(), 42);
+
+        handle.join().unwrap();
+        assert_eq!(Ark::strong_count(&d), 1);
+    });
+}
+
+#[test]
+fn last_drop_happens_after_all_reads() {
+    loom::model(|| {
+        let a = Ark::new(Data::new(42));
+        let b = Ark::clone(&a);
+
+        let handle = thread::spawn(move || {
+            assert_eq!(b.get(), 42);
+            drop(b);
+        });
+
+        assert_eq!(a.get(), 42);
+        drop(a);
+
+        handle.join().unwrap();
+    });
+}
+
+#[test]
+fn upgrade_races_with_last_drop() {
+    loom::model(|| {
+        let a = Ark::new(Data::new(42));
+        let w = Ark::downgrade(&a);
+
+        let handle = thread::spawn(move || {
+            if let Some(b) = w.upgrade() {
+                assert_eq!(b.get(), 42);
+            }
+        });
+
+        drop(a);
+
+        handle.join().unwrap();
+    });
+}
+
+#[test]
+fn get_mut_races_with_upgrade_and_downgrade() {
+    loom::model(|| {
+        let mut a = Ark::new(Data::new(42));
+        let w = Ark::downgrade(&a);
+
+        let handle = thread::spawn(move || {
+            if let Some(b) = w.upgrade() {
+                let w2 = Ark::downgrade(&b);
+                assert_eq!(b.get(), 42);
+                drop(b);
+                drop(w2);
+            }
+        });
+
+        if let Some(data) = Ark::get_mut(&mut a) {
+            data.set(43);
+        }
+
+        handle.join().unwrap();
+    });
+}
+
+#[test]
+fn make_mut_races_with_drop() {
+    loom::model(|| {
+        let mut a = Ark::new(Data::new(42));
+        let b = Ark::clone(&a);
+
+        let handle = thread::spawn(move || {
+            assert_eq!(b.get(), 42);
+            drop(b);
+        });
+
+        // Depending on the interleaving, this either clones the data or mutates it in place.
+        Ark::make_mut(&mut a).set(43);
+        assert_eq!(a.get(), 43);
+
+        handle.join().unwrap();
+    });
+}
+
+#[test]
+fn into_inner_races_yield_exactly_one_value() {
+    loom::model(|| {
+        let a = Ark::new(Data::new(42));
+        let b = Ark::clone(&a);
+
+        let handle = thread::spawn(move || Ark::into_inner(b).map(|data| data.get()));
+
+        let mine = Ark::into_inner(a).map(|data| data.get());
+        let theirs = handle.join().unwrap();
+
+        assert_eq!(mine.xor(theirs), Some(42));
+    });
+}
+
+#[test]
+fn try_unwrap_happens_after_other_drops() {
+    loom::model(|| {
+        let a = Ark::new(Data::new(42));
+        let b = Ark::clone(&a);
+
+        let handle = thread::spawn(move || {
+            assert_eq!(b.get(), 42);
+            drop(b);
+        });
+
+        if let Ok(mut data) = Ark::try_unwrap(a) {
+            data.set(43);
+        }
+
+        handle.join().unwrap();
+    });
+}
+
+#[test]
+fn swap_load_races_with_store() {
+    use ark::ArkSwap;
+    use loom::sync::Arc;
+
+    loom::model(|| {
+        let swap = Arc::new(ArkSwap::new(Ark::new(Data::new(42))));
+
+        let reader = {
+            let swap = Arc::clone(&swap);
+            thread::spawn(move || {
+                let value = swap.load().get();
+                assert!(value == 42 || value == 43);
+            })
+        };
+
+        swap.store(Ark::new(Data::new(43)));
+        assert_eq!(swap.load().get(), 43);
+
+        reader.join().unwrap();
+    });
+}
+
+#[test]
+fn swap_compare_and_swap_races_with_load() {
+    use ark::ArkSwap;
+    use loom::sync::Arc;
+
+    loom::model(|| {
+        let swap = Arc::new(ArkSwap::new(Ark::new(Data::new(42))));
+
+        let reader = {
+            let swap = Arc::clone(&swap);
+            thread::spawn(move || {
+                let value = swap.load().get();
+                assert!(value == 42 || value == 43);
+            })
+        };
+
+        let current = swap.load();
+        let prev = swap
+            .compare_and_swap(&current, Ark::new(Data::new(43)))
+            .ok()
+            .unwrap();
+        assert!(Ark::ptr_eq(&prev, &current));
+        drop((current, prev));
+
+        reader.join().unwrap();
+    });
+}
+
+// A failed exchange must protect the value that it found while a writer may be replacing (and
+// dropping) it.
+#[test]
+fn swap_compare_and_swap_fails_while_racing_with_store() {
+    use ark::ArkSwap;
+    use loom::sync::Arc;
+
+    loom::model(|| {
+        let stale = Ark::new(Data::new(41));
+        let swap = Arc::new(ArkSwap::new(Ark::new(Data::new(42))));
+
+        let writer = {
+            let swap = Arc::clone(&swap);
+            thread::spawn(move || swap.store(Ark::new(Data::new(43))))
+        };
+
+        let (actual, new) = swap
+            .compare_and_swap(&stale, Ark::new(Data::new(44)))
+            .err()
+            .unwrap();
+        let value = actual.get();
+        assert!(value == 42 || value == 43);
+        assert_eq!(new.get(), 44);
+        assert_eq!(Ark::strong_count(&new), 1);
+        drop((actual, new));
+
+        writer.join().unwrap();
+        assert_eq!(swap.load().get(), 43);
+    });
+}
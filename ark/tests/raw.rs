@@ -0,0 +1,82 @@
+// These exercise the pointer arithmetic in Ark::from_raw() and friends, and are mostly useful
+// when run under Miri (`cargo +nightly miri test --test raw`), which checks that the recovered
+// pointers are in bounds and have the right provenance.
+
+// See tests/ark.rs.
+#![cfg(not(feature = "loom"))]
+
+use ark::Ark;
+use std::{fmt::Debug, thread};
+
+#[test]
+fn into_raw_points_at_the_data() {
+    let a = Ark::new(42u8);
+    let ptr = Ark::into_raw(a);
+
+    assert_eq!(unsafe { *ptr }, 42);
+
+    let a = unsafe { Ark::from_raw(ptr) };
+    assert_eq!(Ark::as_ptr(&a), ptr);
+    assert_eq!(*a, 42);
+}
+
+#[test]
+fn round_trips_overaligned_data() {
+    #[repr(align(64))]
+    struct Aligned(u8);
+
+    let ptr = Ark::into_raw(Ark::new(Aligned(7)));
+    assert_eq!(ptr as usize % 64, 0);
+
+    let a = unsafe { Ark::from_raw(ptr) };
+    assert_eq!(a.0, 7);
+}
+
+#[test]
+fn round_trips_unsized_data() {
+    let s: Ark<str> = Ark::from("hello");
+    let ptr = Ark::into_raw(s);
+    let s = unsafe { Ark::from_raw(ptr) };
+    assert_eq!(&*s, "hello");
+
+    let d: Ark<dyn Debug> = Ark::from(Box::new(42u16) as Box<dyn Debug>);
+    let ptr = Ark::into_raw(d);
+    let d = unsafe { Ark::from_raw(ptr) };
+    assert_eq!(format!("{:?}", &*d), "42");
+}
+
+#[test]
+fn strong_count_can_be_managed_through_raw_pointers() {
+    let a = Ark::new(String::from("hi"));
+    let w = Ark::downgrade(&a);
+    let ptr = Ark::into_raw(a);
+
+    unsafe { Ark::increment_strong_count(ptr) };
+    assert_eq!(w.strong_count(), 2);
+
+    unsafe { Ark::decrement_strong_count(ptr) };
+    assert_eq!(w.strong_count(), 1);
+
+    unsafe { Ark::decrement_strong_count(ptr) };
+    assert!(w.upgrade().is_none());
+}
+
+#[test]
+fn raw_pointers_can_cross_threads() {
+    // Raw pointers are not Send, which is exactly the situation with C callbacks' user data; the
+    // pointer (and its provenance) is wrapped instead of being passed around as an integer.
+    struct SendPtr(*const i32);
+    unsafe impl Send for SendPtr {}
+
+    let a = Ark::new(42);
+    let ptr = SendPtr(Ark::into_raw(Ark::clone(&a)));
+
+    thread::spawn(move || {
+        let b = unsafe { Ark::from_raw(ptr.0) };
+        assert_eq!(*b, 42);
+    })
+    .join()
+    .unwrap();
+
+    assert_eq!(Ark::strong_count(&a), 1);
+}
@@ -0,0 +1,24 @@
+#![cfg(all(feature = "serde", not(feature = "loom")))]
+
+use ark::Ark;
+
+#[test]
+fn serializes_the_inner_value() {
+    let a = Ark::new(vec![1, 2, 3]);
+    assert_eq!(serde_json::to_string(&a).unwrap(), "[1,2,3]");
+
+    let s: Ark<str> = Ark::from("hi");
+    assert_eq!(serde_json::to_string(&s).unwrap(), "\"hi\"");
+}
+
+#[test]
+fn deserializes_sized_and_unsized_values() {
+    let a: Ark<Vec<i32>> = serde_json::from_str("[1,2,3]").unwrap();
+    assert_eq!(*a, [1, 2, 3]);
+
+    let s: Ark<str> = serde_json::from_str("\"hi\"").unwrap();
+    assert_eq!(&*s, "hi");
+
+    let v: Ark<[i32]> = serde_json::from_str("[1,2,3]").unwrap();
+    assert_eq!(&*v, [1, 2, 3]);
+}
@@ -0,0 +1,128 @@
+// See tests/ark.rs.
+#![cfg(not(feature = "loom"))]
+
+use ark::{Ark, ArkSwap};
+use std::{
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        Arc,
+    },
+    thread,
+};
+
+#[test]
+fn load_store_and_swap() {
+    let swap = ArkSwap::new(Ark::new(1));
+    assert_eq!(*swap.load(), 1);
+
+    swap.store(Ark::new(2));
+    assert_eq!(*swap.load(), 2);
+
+    let old = swap.swap(Ark::new(3));
+    assert_eq!(*old, 2);
+    assert_eq!(*swap.load(), 3);
+
+    assert_eq!(*swap.into_inner(), 3);
+}
+
+#[test]
+fn compare_and_swap_only_replaces_the_expected_value() {
+    let swap = ArkSwap::new(Ark::new(1));
+
+    let current = swap.load();
+    let prev = swap.compare_and_swap(&current, Ark::new(2)).unwrap();
+    assert!(Ark::ptr_eq(&prev, &current));
+    assert_eq!(*swap.load(), 2);
+
+    // `current` is stale now, even though an equal value is stored.
+    let new = Ark::new(3);
+    let (actual, rejected) = swap
+        .compare_and_swap(&current, Ark::clone(&new))
+        .unwrap_err();
+    assert_eq!(*actual, 2);
+    assert!(Ark::ptr_eq(&actual, &swap.load()));
+    assert!(Ark::ptr_eq(&rejected, &new));
+    assert_eq!(Ark::strong_count(&new), 2);
+    assert_eq!(*swap.load(), 2);
+
+    // The value that was found can be used to retry.
+    let prev = swap.compare_and_swap(&actual, rejected).unwrap();
+    assert!(Ark::ptr_eq(&prev, &actual));
+    assert!(Ark::ptr_eq(&swap.load(), &new));
+}
+
+// The returned value tells whether the exchange happened, even if the expected allocation is
+// reused for another value (which a later comparison of pointers could mistake for success).
+#[test]
+fn compare_and_swap_failures_are_reported_under_aba() {
+    let swap = ArkSwap::new(Ark::new(1));
+    let current = swap.load();
+
+    // The exchange fails while the value is replaced, and succeeds once the same allocation is
+    // put back.
+    let replaced = swap.swap(Ark::new(2));
+    let result = swap.compare_and_swap(&current, Ark::new(3));
+    let (actual, new) = result.unwrap_err();
+    assert_eq!(*actual, 2);
+    assert_eq!(*new, 3);
+
+    swap.store(replaced);
+    let prev = swap.compare_and_swap(&current, new).unwrap();
+    assert!(Ark::ptr_eq(&prev, &current));
+    assert_eq!(*swap.load(), 3);
+}
+
+// Every snapshot is checked to still be intact (not dropped) while writers replace it.
+#[test]
+fn readers_never_observe_freed_values() {
+    const READERS: usize = 4;
+    const WRITES: usize = 10_000;
+
+    struct Snapshot {
+        version: usize,
+        alive: bool,
+    }
+
+    impl Drop for Snapshot {
+        fn drop(&mut self) {
+            assert!(self.alive);
+            self.alive = false;
+        }
+    }
+
+    let swap = Arc::new(ArkSwap::new(Ark::new(Snapshot {
+        version: 0,
+        alive: true,
+    })));
+    let done = Arc::new(AtomicBool::new(false));
+
+    let readers: Vec<_> = (0..READERS)
+        .map(|_| {
+            let swap = Arc::clone(&swap);
+            let done = Arc::clone(&done);
+            thread::spawn(move || {
+                let mut last = 0;
+                while !done.load(Ordering::Relaxed) {
+                    let snapshot = swap.load();
+                    assert!(snapshot.alive);
+                    assert!(snapshot.version >= last);
+                    last = snapshot.version;
+                }
+            })
+        })
+        .collect();
+
+    for version in 1..=WRITES {
+        swap.store(Ark::new(Snapshot {
+            version,
+            alive: true,
+        }));
+    }
+
+    done.store(true, Ordering::Relaxed);
+    for reader in readers {
+        reader.join().unwrap();
+    }
+
+    assert_eq!(swap.load().version, WRITES);
+}
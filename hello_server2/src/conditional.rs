@@ -0,0 +1,260 @@
+//! Conditional and range requests, evaluated against a complete response and its validators.
+
+use std::mem;
+
+use uuid::Uuid;
+
+use crate::http::{self, Status};
+use crate::request::Request;
+use crate::response::{Body, Part, Response};
+
+/// How many ranges to serve in a single response; requests for more get the full response.
+const MAX_RANGES: usize = 16;
+
+/// Evaluates the preconditions (`If-None-Match` and `If-Modified-Since`) and the `Range` of a
+/// `GET` or `HEAD` request against its successful response, which becomes a 304, a 206 or a 416
+/// as appropriate.
+pub fn evaluate(request: &Request, response: Response) -> Response {
+    if response.status != Status::OK || !matches!(request.method.as_str(), "GET" | "HEAD") {
+        return response;
+    }
+
+    if !modified(request, &response) {
+        return not_modified(response);
+    }
+
+    // Ranges only apply to GET.
+    if request.method == "GET" {
+        if let Some(range) = request.headers.get("Range") {
+            if if_range(request, &response) {
+                return ranges(range, response);
+            }
+        }
+    }
+
+    response
+}
+
+// Whether the representation was modified according to `If-None-Match` or, only if that is
+// absent, `If-Modified-Since`.
+fn modified(request: &Request, response: &Response) -> bool {
+    let etag = response.headers.get("ETag");
+
+    let mut if_none_match = request
+        .headers
+        .get_all("If-None-Match")
+        .flat_map(|value| value.split(','))
+        .map(|tag| tag.trim())
+        .filter(|tag| !tag.is_empty())
+        .peekable();
+
+    if if_none_match.peek().is_some() {
+        return !if_none_match.any(|tag| tag == "*" || etag.is_some_and(|e| weak_eq(tag, e)));
+    }
+
+    let since = request.headers.get("If-Modified-Since");
+    let last_modified = response.headers.get("Last-Modified");
+    match (
+        since.and_then(http::parse_date),
+        last_modified.and_then(http::parse_date),
+    ) {
+        (Some(since), Some(last_modified)) => last_modified > since,
+        _ => true,
+    }
+}
+
+// Whether the `Range` should be honored: either there is no `If-Range`, or it (strongly) matches
+// the current representation.
+fn if_range(request: &Request, response: &Response) -> bool {
+    let validator = match request.headers.get("If-Range") {
+        Some(validator) => validator.trim(),
+        None => return true,
+    };
+
+    if validator.starts_with('"') {
+        response.headers.get("ETag") == Some(validator)
+    } else {
+        response.headers.get("Last-Modified") == Some(validator)
+    }
+}
+
+fn weak_eq(a: &str, b: &str) -> bool {
+    a.trim_start_matches("W/") == b.trim_start_matches("W/")
+}
+
+fn not_modified(response: Response) -> Response {
+    let mut not_modified = Response::new(Status::NOT_MODIFIED);
+    for &name in &["ETag", "Last-Modified"] {
+        if let Some(value) = response.headers.get(name) {
+            not_modified = not_modified.with_header(name, value);
+        }
+    }
+    not_modified
+}
+
+fn ranges(range: &str, mut response: Response) -> Response {
+    let len = match response.body {
+        Body::File(_, len) => len,
+        _ => return response,
+    };
+
+    let ranges = match parse_ranges(range, len) {
+        Some(ranges) if ranges.len() <= MAX_RANGES => ranges,
+        _ => return response,
+    };
+
+    if ranges.is_empty() {
+        return Response::plain(Status::RANGE_NOT_SATISFIABLE)
+            .with_header("Content-Range", format!("bytes */{}", len));
+    }
+
+    let file = match mem::replace(&mut response.body, Body::Bytes(Vec::new())) {
+        Body::File(file, _) => file,
+        _ => unreachable!(),
+    };
+    response.status = Status::PARTIAL_CONTENT;
+
+    if let [(start, end)] = ranges[..] {
+        let part = Part {
+            head: Vec::new(),
+            start,
+            len: end - start + 1,
+        };
+        response.body = Body::Ranges(file, vec![part], Vec::new());
+        return response.with_header("Content-Range", format!("bytes {}-{}/{}", start, end, len));
+    }
+
+    let boundary = Uuid::new_v4().to_simple().to_string();
+    let content_type = response
+        .headers
+        .get("Content-Type")
+        .unwrap_or("application/octet-stream")
+        .to_string();
+
+    let parts = ranges
+        .iter()
+        .enumerate()
+        .map(|(i, &(start, end))| {
+            let head = format!(
+                "{}--{}\r\nContent-Type: {}\r\nContent-Range: bytes {}-{}/{}\r\n\r\n",
+                if i == 0 { "" } else { "\r\n" },
+                boundary,
+                content_type,
+                start,
+                end,
+                len
+            );
+            Part {
+                head: head.into_bytes(),
+                start,
+                len: end - start + 1,
+            }
+        })
+        .collect();
+    let trailer = format!("\r\n--{}--\r\n", boundary).into_bytes();
+
+    response.body = Body::Ranges(file, parts, trailer);
+    response.with_header(
+        "Content-Type",
+        format!("multipart/byteranges; boundary={}", boundary),
+    )
+}
+
+// Parses a `Range` header for a representation of `len` bytes into the inclusive bounds of the
+// satisfiable ranges, in the order requested.  Returns `None` if the header is invalid or uses
+// another unit, in which case it should be ignored.
+fn parse_ranges(range: &str, len: u64) -> Option<Vec<(u64, u64)>> {
+    let (unit, specs) = range.split_at(range.find('=')?);
+    if !unit.trim().eq_ignore_ascii_case("bytes") {
+        return None;
+    }
+
+    let specs = &specs[1..];
+    if specs.split(',').all(|spec| spec.trim().is_empty()) {
+        return None;
+    }
+
+    let mut ranges = Vec::new();
+    for spec in specs.split(',').map(|spec| spec.trim()) {
+        // Empty list elements are allowed, and ignored.
+        if spec.is_empty() {
+            continue;
+        }
+
+        let (first, last) = spec.split_at(spec.find('-')?);
+        let (first, last) = (first.trim(), last[1..].trim());
+        let number = |s: &str| -> Option<u64> {
+            if s.bytes().all(|b| b.is_ascii_digit()) {
+                s.parse().ok()
+            } else {
+                None
+            }
+        };
+
+        let bounds = if first.is_empty() {
+            // A suffix: the last `last` bytes.
+            let suffix = number(last)?;
+            if suffix == 0 || len == 0 {
+                None
+            } else {
+                Some((len.saturating_sub(suffix), len - 1))
+            }
+        } else {
+            let first = number(first)?;
+            let last = if last.is_empty() {
+                u64::MAX
+            } else {
+                number(last)?
+            };
+            if last < first {
+                return None;
+            }
+            if first >= len {
+                None
+            } else {
+                Some((first, last.min(len - 1)))
+            }
+        };
+
+        ranges.extend(bounds);
+    }
+
+    Some(ranges)
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn parses_byte_ranges() {
+        assert_eq!(parse_ranges("bytes=0-499", 1000), Some(vec![(0, 499)]));
+        assert_eq!(parse_ranges("bytes=500-", 1000), Some(vec![(500, 999)]));
+        assert_eq!(parse_ranges("bytes=-200", 1000), Some(vec![(800, 999)]));
+        assert_eq!(parse_ranges("bytes=-2000", 1000), Some(vec![(0, 999)]));
+        assert_eq!(parse_ranges("bytes=900-2000", 1000), Some(vec![(900, 999)]));
+        assert_eq!(
+            parse_ranges("Bytes = 0-0 , -1,, 10-19", 1000),
+            Some(vec![(0, 0), (999, 999), (10, 19)])
+        );
+
+        // Syntactically valid, but unsatisfiable.
+        assert_eq!(parse_ranges("bytes=1000-", 1000), Some(vec![]));
+        assert_eq!(parse_ranges("bytes=-0", 1000), Some(vec![]));
+        assert_eq!(parse_ranges("bytes=0-", 0), Some(vec![]));
+        assert_eq!(
+            parse_ranges("bytes=1000-1999,0-9", 1000),
+            Some(vec![(0, 9)])
+        );
+
+        // Invalid, or in another unit.
+        assert_eq!(parse_ranges("bytes=", 1000), None);
+        assert_eq!(parse_ranges("bytes= , ", 1000), None);
+        assert_eq!(parse_ranges("bytes=5-4", 1000), None);
+        assert_eq!(parse_ranges("bytes=a-b", 1000), None);
+        assert_eq!(parse_ranges("bytes=+1-2", 1000), None);
+        assert_eq!(parse_ranges("bytes=1", 1000), None);
+        assert_eq!(parse_ranges("items=0-1", 1000), None);
+        assert_eq!(parse_ranges("0-1", 1000), None);
+    }
+}
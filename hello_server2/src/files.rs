@@ -0,0 +1,414 @@
+//! Static files, served from a document root.
+
+use std::fs::{self, File};
+use std::io;
+use std::path::{Path, PathBuf};
+use std::time::UNIX_EPOCH;
+
+use crate::http::{self, Status};
+use crate::response::Response;
+
+pub struct Files {
+    // Canonical, so that it can be compared with the canonical paths of the files.
+    root: PathBuf,
+
+    // Whether to list the contents of directories without an index.html.
+    listings: bool,
+}
+
+impl Files {
+    pub fn new(root: impl AsRef<Path>, listings: bool) -> io::Result<Files> {
+        Ok(Files {
+            root: root.as_ref().canonicalize()?,
+            listings,
+        })
+    }
+
+    /// Serves the file or directory for a request target; errors are returned as the status of
+    /// the response, so the caller can choose how to present them.
+    pub fn serve(&self, target: &str) -> Result<Response, Status> {
+        let path = decode_path(target)?;
+
+        let mut file = self.root.clone();
+        for segment in path.split('/') {
+            match segment {
+                "" | "." => {}
+                ".." => return Err(Status::FORBIDDEN),
+                _ => file.push(segment),
+            }
+        }
+
+        // Resolve any symbolic links, and check that they do not lead out of the root.
+        let file = file.canonicalize().map_err(|_| Status::NOT_FOUND)?;
+        if !file.starts_with(&self.root) {
+            return Err(Status::FORBIDDEN);
+        }
+
+        if file.is_dir() {
+            // Relative links (in index.html or in a listing) only work from a path with a
+            // trailing slash.  Leading slashes are collapsed, since a Location starting with `//`
+            // would be taken as the authority of another host (e.g. `//evil.example/`).
+            if !path.ends_with('/') {
+                let path = target.split('?').next().unwrap();
+                let location = format!("/{}/", path.trim_start_matches('/'));
+                return Ok(
+                    Response::plain(Status::MOVED_PERMANENTLY).with_header("Location", location)
+                );
+            }
+
+            let index = file.join("index.html");
+            if index.is_file() {
+                return self.serve_file(&index);
+            }
+
+            if self.listings {
+                return listing(&file, &path).map_err(|_| Status::NOT_FOUND);
+            }
+
+            return Err(Status::NOT_FOUND);
+        }
+
+        self.serve_file(&file)
+    }
+
+    /// Builds the response for an error status, using the page for it in the document root (like
+    /// `404.html`) as the body, if there is one.
+    pub fn error_page(&self, status: Status) -> Response {
+        match fs::read(self.root.join(format!("{}.html", status.0))) {
+            Ok(page) => Response::new(status).with_body("text/html; charset=utf-8", page),
+            Err(_) => Response::plain(status),
+        }
+    }
+
+    fn serve_file(&self, path: &Path) -> Result<Response, Status> {
+        let file = File::open(path).map_err(|_| Status::NOT_FOUND)?;
+        let metadata = file.metadata().map_err(|_| Status::NOT_FOUND)?;
+        let len = metadata.len();
+
+        let mut response = Response::new(Status::OK)
+            .with_header("Accept-Ranges", "bytes")
+            .with_file(content_type(path), file, len);
+
+        // Validators, for conditional requests; the ETag changes with the modification time
+        // (at full precision) or the size.
+        if let Ok(modified) = metadata.modified() {
+            let nanos = modified
+                .duration_since(UNIX_EPOCH)
+                .map_or(0, |since| since.as_nanos());
+            response = response
+                .with_header("ETag", format!("\"{:x}-{:x}\"", nanos, len))
+                .with_header("Last-Modified", http::format_date(modified));
+        }
+
+        Ok(response)
+    }
+}
+
+// Returns the percent-decoded path of an (origin-form) request target, without the query.
+fn decode_path(target: &str) -> Result<String, Status> {
+    let path = target.split('?').next().unwrap();
+
+    if !path.starts_with('/') {
+        return Err(Status::BAD_REQUEST);
+    }
+
+    let path = http::percent_decode(path).ok_or(Status::BAD_REQUEST)?;
+
+    // No file name can contain NUL, or backslashes (which Windows would treat as separators).
+    if path.contains('\0') || path.contains('\\') {
+        return Err(Status::BAD_REQUEST);
+    }
+
+    Ok(path)
+}
+
+fn content_type(path: &Path) -> &'static str {
+    let extension = path
+        .extension()
+        .and_then(|ext| ext.to_str())
+        .map(|ext| ext.to_ascii_lowercase());
+
+    match extension.as_deref() {
+        Some("html") | Some("htm") => "text/html; charset=utf-8",
+        Some("css") => "text/css; charset=utf-8",
+        Some("js") | Some("mjs") => "text/javascript; charset=utf-8",
+        Some("json") => "application/json",
+        Some("txt") | Some("md") => "text/plain; charset=utf-8",
+        Some("xml") => "application/xml",
+        Some("png") => "image/png",
+        Some("jpg") | Some("jpeg") => "image/jpeg",
+        Some("gif") => "image/gif",
+        Some("svg") => "image/svg+xml",
+        Some("ico") => "image/x-icon",
+        Some("webp") => "image/webp",
+        Some("woff") => "font/woff",
+        Some("woff2") => "font/woff2",
+        Some("wasm") => "application/wasm",
+        Some("pdf") => "application/pdf",
+        _ => "application/octet-stream",
+    }
+}
+
+fn listing(dir: &Path, path: &str) -> io::Result<Response> {
+    let mut entries = fs::read_dir(dir)?
+        .filter_map(|entry| entry.ok())
+        .filter_map(|entry| {
+            let name = entry.file_name().into_string().ok()?;
+            let is_dir = entry.path().is_dir();
+            Some((name, is_dir))
+        })
+        .collect::<Vec<_>>();
+    entries.sort();
+
+    let title = format!("Index of {}", escape_html(path));
+    let mut html = format!(
+        "<!DOCTYPE html>\n<html lang=\"en\">\n  <head>\n    <meta charset=\"utf-8\">\n    \
+         <title>{0}</title>\n  </head>\n  <body>\n    <h1>{0}</h1>\n    <ul>\n",
+        title
+    );
+
+    if path != "/" {
+        html.push_str("      <li><a href=\"../\">../</a></li>\n");
+    }
+
+    for (name, is_dir) in entries {
+        let slash = if is_dir { "/" } else { "" };
+        html.push_str(&format!(
+            "      <li><a href=\"{}{}\">{}{}</a></li>\n",
+            encode_segment(&name),
+            slash,
+            escape_html(&name),
+            slash
+        ));
+    }
+
+    html.push_str("    </ul>\n  </body>\n</html>\n");
+
+    Ok(Response::new(Status::OK).with_body("text/html; charset=utf-8", html))
+}
+
+fn encode_segment(segment: &str) -> String {
+    segment
+        .bytes()
+        .map(|b| match b {
+            b'A'..=b'Z' | b'a'..=b'z' | b'0'..=b'9' | b'-' | b'.' | b'_' | b'~' => {
+                (b as char).to_string()
+            }
+            _ => format!("%{:02X}", b),
+        })
+        .collect()
+}
+
+fn escape_html(s: &str) -> String {
+    s.chars()
+        .map(|c| match c {
+            '&' => "&amp;".to_string(),
+            '<' => "&lt;".to_string(),
+            '>' => "&gt;".to_string(),
+            '"' => "&quot;".to_string(),
+            '\'' => "&#39;".to_string(),
+            _ => c.to_string(),
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod test {
+    use std::env;
+    use std::process;
+
+    use super::*;
+    use crate::response::Body;
+
+    // A document root with a few files, next to a secret file outside of it.
+    struct Fixture {
+        dir: PathBuf,
+    }
+
+    impl Fixture {
+        fn new(name: &str) -> Fixture {
+            let dir = env::temp_dir().join(format!("hello_server2-{}-{}", process::id(), name));
+            let _ = fs::remove_dir_all(&dir);
+
+            fs::create_dir_all(dir.join("root/docs")).unwrap();
+            fs::create_dir_all(dir.join("root/site")).unwrap();
+            fs::write(dir.join("secret.txt"), "secret").unwrap();
+            fs::write(dir.join("root/hello world.txt"), "hello").unwrap();
+            fs::write(dir.join("root/style.CSS"), "body {}").unwrap();
+            fs::write(dir.join("root/docs/<b>.md"), "# docs").unwrap();
+            fs::write(dir.join("root/site/index.html"), "<p>site</p>").unwrap();
+
+            #[cfg(unix)]
+            {
+                use std::os::unix::fs::symlink;
+                symlink(dir.join("secret.txt"), dir.join("root/escape.txt")).unwrap();
+                symlink(dir.join("root/docs"), dir.join("root/inside")).unwrap();
+            }
+
+            Fixture { dir }
+        }
+
+        fn files(&self, listings: bool) -> Files {
+            Files::new(self.dir.join("root"), listings).unwrap()
+        }
+    }
+
+    impl Drop for Fixture {
+        fn drop(&mut self) {
+            let _ = fs::remove_dir_all(&self.dir);
+        }
+    }
+
+    fn body(response: Response) -> Vec<u8> {
+        let mut bytes = Vec::new();
+        response.write_to(&mut bytes).unwrap();
+        let start = bytes.windows(4).position(|w| w == b"\r\n\r\n").unwrap() + 4;
+        bytes.split_off(start)
+    }
+
+    fn status(result: Result<Response, Status>) -> Status {
+        match result {
+            Ok(response) => response.status,
+            Err(status) => status,
+        }
+    }
+
+    #[test]
+    fn serves_files_with_content_types() {
+        let fixture = Fixture::new("types");
+        let files = fixture.files(false);
+
+        let response = files.serve("/hello%20world.txt?download=1").unwrap();
+        assert_eq!(
+            response.headers.get("Content-Type"),
+            Some("text/plain; charset=utf-8")
+        );
+        assert!(matches!(response.body, Body::File(_, 5)));
+        assert_eq!(response.headers.get("Accept-Ranges"), Some("bytes"));
+        assert_eq!(body(response), b"hello");
+
+        let response = files.serve("/style.CSS").unwrap();
+        assert_eq!(
+            response.headers.get("Content-Type"),
+            Some("text/css; charset=utf-8")
+        );
+
+        assert_eq!(status(files.serve("/missing.txt")), Status::NOT_FOUND);
+    }
+
+    #[test]
+    fn serves_index_html_and_redirects_directories() {
+        let fixture = Fixture::new("index");
+        let files = fixture.files(false);
+
+        let response = files.serve("/site/").unwrap();
+        assert_eq!(body(response), b"<p>site</p>");
+
+        let response = files.serve("/site?x=1").unwrap();
+        assert_eq!(response.status, Status::MOVED_PERMANENTLY);
+        assert_eq!(response.headers.get("Location"), Some("/site/"));
+
+        // Not an open redirect to another host.
+        for target in &["//site", "///site?x=1"] {
+            let response = files.serve(target).unwrap();
+            assert_eq!(response.status, Status::MOVED_PERMANENTLY);
+            assert_eq!(
+                response.headers.get("Location"),
+                Some("/site/"),
+                "{}",
+                target
+            );
+        }
+
+        // No index.html and no listings.
+        assert_eq!(status(files.serve("/docs/")), Status::NOT_FOUND);
+    }
+
+    #[test]
+    fn lists_directories_when_enabled() {
+        let fixture = Fixture::new("listings");
+        let files = fixture.files(true);
+
+        let html = String::from_utf8(body(files.serve("/docs/").unwrap())).unwrap();
+        assert!(html.contains("<title>Index of /docs/</title>"));
+        assert!(html.contains("<a href=\"../\">"));
+        assert!(html.contains("<a href=\"%3Cb%3E.md\">&lt;b&gt;.md</a>"));
+
+        let html = String::from_utf8(body(files.serve("/").unwrap())).unwrap();
+        assert!(html.contains("<a href=\"docs/\">docs/</a>"));
+        assert!(html.contains("<a href=\"hello%20world.txt\">"));
+    }
+
+    #[test]
+    fn error_pages_come_from_the_root() {
+        let fixture = Fixture::new("error-pages");
+        fs::write(fixture.dir.join("root/404.html"), "<p>not here</p>").unwrap();
+        let files = fixture.files(false);
+
+        assert_eq!(
+            body(files.error_page(Status::NOT_FOUND)),
+            b"<p>not here</p>"
+        );
+
+        let forbidden = files.error_page(Status::FORBIDDEN);
+        assert_eq!(forbidden.status, Status::FORBIDDEN);
+        assert_eq!(
+            forbidden.headers.get("Content-Type"),
+            Some("text/plain; charset=utf-8")
+        );
+    }
+
+    #[test]
+    fn rejects_traversal() {
+        let fixture = Fixture::new("traversal");
+        let files = fixture.files(true);
+
+        assert_eq!(status(files.serve("/../secret.txt")), Status::FORBIDDEN);
+        assert_eq!(
+            status(files.serve("/docs/%2e%2e/../secret.txt")),
+            Status::FORBIDDEN
+        );
+        assert_eq!(
+            status(files.serve("/docs%2F..%2F..%2Fsecret.txt")),
+            Status::FORBIDDEN
+        );
+        assert_eq!(status(files.serve("/%00")), Status::BAD_REQUEST);
+        assert_eq!(status(files.serve("/%zz")), Status::BAD_REQUEST);
+        assert_eq!(status(files.serve("/%ff")), Status::BAD_REQUEST);
+        assert_eq!(status(files.serve("..\\secret.txt")), Status::BAD_REQUEST);
+        assert_eq!(status(files.serve("/..%5csecret.txt")), Status::BAD_REQUEST);
+    }
+
+    #[test]
+    #[cfg(unix)]
+    fn rejects_symlinks_that_escape_the_root() {
+        let fixture = Fixture::new("symlinks");
+        let files = fixture.files(false);
+
+        assert_eq!(status(files.serve("/escape.txt")), Status::FORBIDDEN);
+        assert_eq!(body(files.serve("/inside/%3Cb%3E.md").unwrap()), b"# docs");
+    }
+
+    #[test]
+    fn generates_validators_that_change_with_the_file() {
+        let fixture = Fixture::new("validators");
+        let files = fixture.files(false);
+
+        let validators = |response: Response| {
+            let etag = response.headers.get("ETag").unwrap().to_string();
+            let modified = response.headers.get("Last-Modified").unwrap().to_string();
+            (etag, modified)
+        };
+
+        let (etag, modified) = validators(files.serve("/hello%20world.txt").unwrap());
+        assert!(etag.starts_with('"') && etag.ends_with("-5\""));
+        assert!(http::parse_date(&modified).is_some());
+
+        let (same, _) = validators(files.serve("/hello%20world.txt").unwrap());
+        assert_eq!(same, etag);
+
+        fs::write(fixture.dir.join("root/hello world.txt"), "hello, world").unwrap();
+        let (changed, _) = validators(files.serve("/hello%20world.txt").unwrap());
+        assert_ne!(changed, etag);
+    }
+}
@@ -0,0 +1,295 @@
+//! Types shared by requests and responses.
+
+use std::fmt;
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Version {
+    Http10,
+    Http11,
+}
+
+impl fmt::Display for Version {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Version::Http10 => write!(f, "HTTP/1.0"),
+            Version::Http11 => write!(f, "HTTP/1.1"),
+        }
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Status(pub u16);
+
+impl Status {
+    pub const OK: Status = Status(200);
+    pub const PARTIAL_CONTENT: Status = Status(206);
+    pub const MOVED_PERMANENTLY: Status = Status(301);
+    pub const NOT_MODIFIED: Status = Status(304);
+    pub const BAD_REQUEST: Status = Status(400);
+    pub const FORBIDDEN: Status = Status(403);
+    pub const NOT_FOUND: Status = Status(404);
+    pub const METHOD_NOT_ALLOWED: Status = Status(405);
+    pub const PAYLOAD_TOO_LARGE: Status = Status(413);
+    pub const RANGE_NOT_SATISFIABLE: Status = Status(416);
+    pub const HEADER_FIELDS_TOO_LARGE: Status = Status(431);
+    pub const NOT_IMPLEMENTED: Status = Status(501);
+    pub const VERSION_NOT_SUPPORTED: Status = Status(505);
+
+    pub fn reason(self) -> &'static str {
+        match self.0 {
+            200 => "OK",
+            206 => "Partial Content",
+            301 => "Moved Permanently",
+            304 => "Not Modified",
+            400 => "Bad Request",
+            403 => "Forbidden",
+            404 => "Not Found",
+            405 => "Method Not Allowed",
+            413 => "Payload Too Large",
+            416 => "Range Not Satisfiable",
+            431 => "Request Header Fields Too Large",
+            501 => "Not Implemented",
+            505 => "HTTP Version Not Supported",
+            _ => "Unknown",
+        }
+    }
+}
+
+impl fmt::Display for Status {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{} {}", self.0, self.reason())
+    }
+}
+
+/// Header fields, in the order they were added, with case-insensitive lookup by name.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct Headers {
+    fields: Vec<(String, String)>,
+}
+
+impl Headers {
+    pub fn new() -> Headers {
+        Headers::default()
+    }
+
+    pub fn len(&self) -> usize {
+        self.fields.len()
+    }
+
+    /// Returns the value of the first field named `name`.
+    pub fn get(&self, name: &str) -> Option<&str> {
+        self.fields
+            .iter()
+            .find(|(n, _)| n.eq_ignore_ascii_case(name))
+            .map(|(_, v)| v.as_str())
+    }
+
+    /// Returns the values of all fields named `name`.
+    pub fn get_all<'a>(&'a self, name: &'a str) -> impl Iterator<Item = &'a str> + 'a {
+        self.fields
+            .iter()
+            .filter(move |(n, _)| n.eq_ignore_ascii_case(name))
+            .map(|(_, v)| v.as_str())
+    }
+
+    /// Returns whether any field named `name` lists `token` (e.g. `Connection: keep-alive`),
+    /// comparing case-insensitively.
+    pub fn has_token(&self, name: &str, token: &str) -> bool {
+        self.get_all(name)
+            .flat_map(|value| value.split(','))
+            .any(|t| t.trim().eq_ignore_ascii_case(token))
+    }
+
+    /// Adds a field, keeping any others with the same name.
+    pub fn append(&mut self, name: impl Into<String>, value: impl Into<String>) {
+        self.fields.push((name.into(), value.into()));
+    }
+
+    /// Sets a field, replacing any others with the same name.
+    pub fn set(&mut self, name: impl Into<String>, value: impl Into<String>) {
+        let name = name.into();
+        self.remove(&name);
+        self.fields.push((name, value.into()));
+    }
+
+    pub fn remove(&mut self, name: &str) {
+        self.fields.retain(|(n, _)| !n.eq_ignore_ascii_case(name));
+    }
+
+    pub fn iter(&self) -> impl Iterator<Item = (&str, &str)> {
+        self.fields.iter().map(|(n, v)| (n.as_str(), v.as_str()))
+    }
+}
+
+const DAYS: [&str; 7] = ["Sun", "Mon", "Tue", "Wed", "Thu", "Fri", "Sat"];
+const MONTHS: [&str; 12] = [
+    "Jan", "Feb", "Mar", "Apr", "May", "Jun", "Jul", "Aug", "Sep", "Oct", "Nov", "Dec",
+];
+
+/// Formats a time as an HTTP-date (e.g. `Sun, 06 Nov 1994 08:49:37 GMT`), truncated to seconds.
+pub fn format_date(time: SystemTime) -> String {
+    let secs = time
+        .duration_since(UNIX_EPOCH)
+        .map_or(0, |since| since.as_secs());
+    let days = secs / 86_400;
+    let (year, month, day) = civil_from_days(days as i64);
+
+    // 1970-01-01 was a Thursday.
+    format!(
+        "{}, {:02} {} {} {:02}:{:02}:{:02} GMT",
+        DAYS[((days + 4) % 7) as usize],
+        day,
+        MONTHS[month as usize - 1],
+        year,
+        secs % 86_400 / 3600,
+        secs % 3600 / 60,
+        secs % 60
+    )
+}
+
+/// Parses an HTTP-date in the preferred (IMF-fixdate) format; the obsolete formats, and any
+/// dates before 1970, are rejected.
+pub fn parse_date(date: &str) -> Option<SystemTime> {
+    let mut parts = date.split(' ');
+    let (_, day, month, year, time, gmt) = (
+        parts.next()?,
+        parts.next()?,
+        parts.next()?,
+        parts.next()?,
+        parts.next()?,
+        parts.next()?,
+    );
+    if parts.next().is_some() || gmt != "GMT" || day.len() != 2 || year.len() != 4 {
+        return None;
+    }
+
+    let day: i64 = day.parse().ok()?;
+    let month = MONTHS.iter().position(|&m| m == month)? as i64 + 1;
+    let year: i64 = year.parse().ok()?;
+
+    let mut hms = time
+        .split(':')
+        .map(|t| t.parse::<u64>().ok().filter(|_| t.len() == 2));
+    let (h, m, s) = (hms.next()??, hms.next()??, hms.next()??);
+    if hms.next().is_some() || h > 23 || m > 59 || s > 60 || !(1..=31).contains(&day) {
+        return None;
+    }
+
+    let days = days_from_civil(year, month, day);
+    if days < 0 {
+        return None;
+    }
+
+    let secs = days as u64 * 86_400 + h * 3600 + m * 60 + s;
+    Some(UNIX_EPOCH + Duration::from_secs(secs))
+}
+
+/// Decodes the `%XX` escapes in (a part of) a request target; fails on malformed escapes, or if
+/// the result is not UTF-8.
+pub fn percent_decode(s: &str) -> Option<String> {
+    let mut bytes = Vec::with_capacity(s.len());
+    let mut rest = s.as_bytes();
+    while let Some((&b, tail)) = rest.split_first() {
+        if b == b'%' {
+            // from_str_radix() alone would also accept a sign, as in `%+1`.
+            let hex = tail
+                .get(..2)
+                .filter(|hex| hex.iter().all(u8::is_ascii_hexdigit))
+                .and_then(|hex| std::str::from_utf8(hex).ok())
+                .and_then(|hex| u8::from_str_radix(hex, 16).ok())?;
+            bytes.push(hex);
+            rest = &tail[2..];
+        } else {
+            bytes.push(b);
+            rest = tail;
+        }
+    }
+
+    String::from_utf8(bytes).ok()
+}
+
+// Conversions between days since 1970-01-01 and (proleptic Gregorian) dates, from Howard
+// Hinnant's "chrono-Compatible Low-Level Date Algorithms".
+
+fn civil_from_days(days: i64) -> (i64, i64, i64) {
+    let z = days + 719_468;
+    let era = z.div_euclid(146_097);
+    let doe = z.rem_euclid(146_097);
+    let yoe = (doe - doe / 1460 + doe / 36_524 - doe / 146_096) / 365;
+    let doy = doe - (365 * yoe + yoe / 4 - yoe / 100);
+    let mp = (5 * doy + 2) / 153;
+    let day = doy - (153 * mp + 2) / 5 + 1;
+    let month = if mp < 10 { mp + 3 } else { mp - 9 };
+    let year = yoe + era * 400 + if month <= 2 { 1 } else { 0 };
+    (year, month, day)
+}
+
+fn days_from_civil(year: i64, month: i64, day: i64) -> i64 {
+    let year = if month <= 2 { year - 1 } else { year };
+    let era = year.div_euclid(400);
+    let yoe = year.rem_euclid(400);
+    let doy = (153 * (if month > 2 { month - 3 } else { month + 9 }) + 2) / 5 + day - 1;
+    let doe = yoe * 365 + yoe / 4 - yoe / 100 + doy;
+    era * 146_097 + doe - 719_468
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn decodes_percent_escapes() {
+        assert_eq!(percent_decode("a%20b%2Fc").as_deref(), Some("a b/c"));
+        assert_eq!(percent_decode("%C3%A9t%c3%a9").as_deref(), Some("été"));
+        assert_eq!(percent_decode("100%").as_deref(), None);
+        assert_eq!(percent_decode("%2").as_deref(), None);
+        assert_eq!(percent_decode("%+1").as_deref(), None);
+        assert_eq!(percent_decode("%zz").as_deref(), None);
+        assert_eq!(percent_decode("%FF").as_deref(), None);
+    }
+
+    #[test]
+    fn headers_are_case_insensitive() {
+        let mut headers = Headers::new();
+        headers.append("Content-Type", "text/html");
+        headers.append("accept", "text/html");
+        headers.append("Accept", "*/*");
+
+        assert_eq!(headers.get("content-type"), Some("text/html"));
+        assert_eq!(headers.get("CONTENT-TYPE"), Some("text/html"));
+        assert_eq!(
+            headers.get_all("ACCEPT").collect::<Vec<_>>(),
+            ["text/html", "*/*"]
+        );
+        assert_eq!(headers.get("Host"), None);
+
+        headers.set("ACCEPT", "image/png");
+        assert_eq!(headers.get_all("accept").collect::<Vec<_>>(), ["image/png"]);
+        assert_eq!(headers.len(), 2);
+    }
+
+    #[test]
+    fn formats_and_parses_dates() {
+        let time = UNIX_EPOCH + Duration::from_secs(784_111_777);
+        assert_eq!(format_date(time), "Sun, 06 Nov 1994 08:49:37 GMT");
+        assert_eq!(parse_date("Sun, 06 Nov 1994 08:49:37 GMT"), Some(time));
+
+        assert_eq!(format_date(UNIX_EPOCH), "Thu, 01 Jan 1970 00:00:00 GMT");
+        assert_eq!(
+            format_date(UNIX_EPOCH + Duration::from_millis(951_782_400_999)),
+            "Tue, 29 Feb 2000 00:00:00 GMT"
+        );
+
+        for &secs in &[0, 59, 86_399, 951_782_400, 1_700_000_000, 4_102_444_800] {
+            let time = UNIX_EPOCH + Duration::from_secs(secs);
+            assert_eq!(parse_date(&format_date(time)), Some(time), "{}", secs);
+        }
+
+        assert_eq!(parse_date("Sunday, 06-Nov-94 08:49:37 GMT"), None);
+        assert_eq!(parse_date("Sun Nov  6 08:49:37 1994"), None);
+        assert_eq!(parse_date("Sun, 06 Nov 1994 08:49:37 UTC"), None);
+        assert_eq!(parse_date("Sun, 06 Nov 1994 24:00:00 GMT"), None);
+        assert_eq!(parse_date("Wed, 31 Dec 1969 23:59:59 GMT"), None);
+    }
+}
@@ -1,21 +1,31 @@
+mod conditional;
+mod files;
+mod http;
+mod request;
+mod response;
+mod router;
 mod thread_pool;
 
-use std::io::prelude::*;
-use std::io::BufReader;
+use std::io::{self, BufReader, ErrorKind, Read};
 
 use std::net::{TcpListener, TcpStream};
 
 use std::sync::atomic::{AtomicBool, Ordering};
 use std::sync::Arc;
 
-use std::fs;
+use std::env;
 use std::thread;
-use std::time::Duration;
+use std::time::{Duration, Instant};
 
 use uuid::Uuid;
 
 use signal_hook::iterator::Signals;
 
+use files::Files;
+use http::Status;
+use request::{Limits, ParseError, Request};
+use response::Response;
+use router::{Next, Router};
 use thread_pool::ThreadPool;
 
 type Result<T> = std::result::Result<T, Box<dyn std::error::Error>>;
@@ -26,6 +36,17 @@ fn main() -> Result<()> {
     {
         let bind_addr = "0.0.0.0:7878";
 
+        // usage: hello_server2 [--listings] [DOCUMENT_ROOT]
+        let mut document_root = String::from("public");
+        let mut listings = false;
+        for arg in env::args().skip(1) {
+            match arg.as_str() {
+                "--listings" => listings = true,
+                _ => document_root = arg,
+            }
+        }
+        let router = Arc::new(routes(Files::new(&document_root, listings)?));
+
         let alive = Arc::new(AtomicBool::new(true));
 
         let listener = {
@@ -33,7 +54,7 @@ fn main() -> Result<()> {
             let pool = ThreadPool::new(4);
             let alive = Arc::clone(&alive);
 
-            thread::spawn(move || listen(listener, pool, alive))
+            thread::spawn(move || listen(listener, pool, alive, router))
         };
 
         let term_signals = [
@@ -42,7 +63,7 @@ fn main() -> Result<()> {
             signal_hook::SIGQUIT,
         ];
 
-        if let Some(signal) = Signals::new(&term_signals)?.forever().next() {
+        if let Some(signal) = Signals::new(term_signals)?.forever().next() {
             eprintln!("received signal to terminate: {}", signal);
             exit = 128 + signal;
         }
@@ -77,69 +98,614 @@ fn main() -> Result<()> {
     std::process::exit(exit);
 }
 
-fn listen(listener: TcpListener, pool: ThreadPool, alive: Arc<AtomicBool>) {
+fn listen(listener: TcpListener, pool: ThreadPool, alive: Arc<AtomicBool>, router: Arc<Router>) {
     for stream in listener.incoming() {
         if !alive.load(Ordering::SeqCst) {
             break;
         }
 
         if let Ok(stream) = stream {
-            pool.execute(|| {
-                let request_id = Uuid::new_v4();
+            let alive = Arc::clone(&alive);
+            let router = Arc::clone(&router);
+
+            pool.execute(move || {
+                let connection_id = Uuid::new_v4();
 
-                if let Err(err) = handle_connection(request_id, stream) {
-                    eprintln!("[{}] ! error handling request: {:?}", request_id, err);
+                if let Err(err) = handle_connection(connection_id, stream, &alive, &router) {
+                    eprintln!("[{}] ! error handling request: {:?}", connection_id, err);
                 }
             });
         }
     }
 }
 
-fn handle_connection(request_id: Uuid, mut stream: TcpStream) -> Result<()> {
-    let mut reader = BufReader::new(stream.try_clone()?);
-
-    let mut request_line = String::new();
-    reader.read_line(&mut request_line)?;
+/// How long a client has to send each request in full, counting from when the server starts
+/// waiting for it (so this also limits how long a persistent connection can stay idle).
+const REQUEST_TIMEOUT: Duration = Duration::from_secs(10);
+
+/// How long writing each part of a response can block.
+const WRITE_TIMEOUT: Duration = Duration::from_secs(10);
+
+/// How many requests to serve on a single connection before closing it.
+const MAX_REQUESTS: usize = 100;
+
+// Serves requests from the connection until it is closed.  Pipelined requests are simply read
+// from the buffer in turn, so they are processed (and answered) in order, by this same worker.
+fn handle_connection(
+    connection_id: Uuid,
+    mut stream: TcpStream,
+    alive: &AtomicBool,
+    router: &Router,
+) -> Result<()> {
+    stream.set_write_timeout(Some(WRITE_TIMEOUT))?;
+    let mut reader = BufReader::new(DeadlineReader {
+        stream: stream.try_clone()?,
+        deadline: Instant::now(),
+    });
 
     let pretty_peer = match stream.peer_addr() {
         Ok(a) => a.to_string(),
         Err(_) => String::from("unknown peer"),
     };
-    eprintln!(
-        "[{}] < {} from {}",
-        request_id,
-        request_line.trim_end(),
-        pretty_peer
-    );
-
-    let hello = "GET / ";
-    let sleep = "GET /sleep ";
-    let panic = "GET /panic ";
-
-    let (status_line, contents) = if request_line.starts_with(hello) {
-        ("HTTP/1.1 200 OK\r\n", Some("hello.html"))
-    } else if request_line.starts_with(sleep) {
-        thread::sleep(Duration::from_secs(10));
-        ("HTTP/1.1 200 OK\r\n", None)
-    } else if request_line.starts_with(panic) {
-        panic!("Oh no!!!");
-    } else {
-        ("HTTP/1.1 404 Not Found\r\n", Some("404.html"))
-    };
 
-    stream.write_all(status_line.as_bytes())?;
+    for served in 1..=MAX_REQUESTS {
+        reader.get_mut().deadline = Instant::now() + REQUEST_TIMEOUT;
+
+        let request = match Request::read_from(&mut reader, &Limits::default()) {
+            Ok(Some(request)) => request,
+            Ok(None) => break,
+            Err(ParseError::Io(err))
+                if matches!(err.kind(), ErrorKind::WouldBlock | ErrorKind::TimedOut) =>
+            {
+                eprintln!("[{}] timed out waiting for a request", connection_id);
+                break;
+            }
+            Err(err) => {
+                eprintln!("[{}] < {} from {}", connection_id, err, pretty_peer);
+
+                // The rest of the request cannot be trusted, so send a response (if the connection
+                // is still alive) and close it.
+                if let Some(status) = err.status() {
+                    Response::plain(status)
+                        .with_header("Connection", "close")
+                        .write_to(&mut stream)?;
+                    eprintln!("[{}] > {}", connection_id, status);
+                }
+                break;
+            }
+        };
 
-    if let Some(contents) = contents {
-        let contents = fs::read(contents)?;
-        stream.write_all(format!("Content-Length: {}\r\n\r\n", contents.len()).as_bytes())?;
-        stream.write_all(&contents)?;
-    } else {
-        stream.write_all(b"\r\n\r\n")?;
-    }
+        eprintln!("[{}] < {} from {}", connection_id, request, pretty_peer);
+
+        // Also close the connection on the last allowed request, or if shutting down, so that
+        // the pool can be drained.
+        let keep_alive =
+            request.keep_alive() && served < MAX_REQUESTS && alive.load(Ordering::SeqCst);
 
-    stream.flush()?;
+        let response = router.handle(&request);
+
+        let connection = if keep_alive { "keep-alive" } else { "close" };
+        let response = response.with_header("Connection", connection);
+
+        if request.method == "HEAD" {
+            response.write_head_to(&mut stream)?;
+        } else {
+            response.write_to(&mut stream)?;
+        }
 
-    eprintln!("[{}] > {}", request_id, status_line.trim_end());
+        eprintln!("[{}] > {}", connection_id, response.status);
+
+        if !keep_alive {
+            break;
+        }
+    }
 
     Ok(())
 }
+
+// Reads from a stream until a deadline, however the data is spread over time: a timeout on each
+// read alone would let a client that sends one byte at a time hold a worker for as long as it
+// wants.
+struct DeadlineReader {
+    stream: TcpStream,
+    deadline: Instant,
+}
+
+impl Read for DeadlineReader {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        let remaining = self.deadline.saturating_duration_since(Instant::now());
+        if remaining == Duration::from_secs(0) {
+            return Err(io::Error::new(
+                ErrorKind::TimedOut,
+                "request deadline exceeded",
+            ));
+        }
+
+        self.stream.set_read_timeout(Some(remaining))?;
+        self.stream.read(buf)
+    }
+}
+
+fn routes(files: Files) -> Router {
+    // Error pages come from the document root, like the files themselves.
+    let files = Arc::new(files);
+    let not_found = Arc::clone(&files);
+
+    Router::new()
+        .wrap(log_slow_requests)
+        .wrap(server_timing)
+        .get("/sleep", |_, _| {
+            thread::sleep(Duration::from_secs(10));
+            Response::new(Status::OK)
+        })
+        .get("/panic", |_, _| panic!("Oh no!!!"))
+        .get("/hello/:name", |_, params| {
+            let greeting = format!("Hello, {}!\n", params.get("name").unwrap());
+            Response::new(Status::OK).with_body("text/plain; charset=utf-8", greeting)
+        })
+        .get("/*path", move |request, _| {
+            match files.serve(&request.target) {
+                Ok(response) => conditional::evaluate(request, response),
+                Err(status) => files.error_page(status),
+            }
+        })
+        .not_found(move |_| not_found.error_page(Status::NOT_FOUND))
+}
+
+/// How long a request can take before it is logged as slow.
+const SLOW_REQUEST: Duration = Duration::from_secs(1);
+
+fn log_slow_requests(request: &Request, next: Next<'_>) -> Response {
+    let start = Instant::now();
+    let response = next.run(request);
+    let elapsed = start.elapsed();
+    if elapsed >= SLOW_REQUEST {
+        eprintln!("slow request: {} took {:.1?}", request, elapsed);
+    }
+    response
+}
+
+// Reports how long the request took to handle, in a `Server-Timing` header (in milliseconds).
+fn server_timing(request: &Request, next: Next<'_>) -> Response {
+    let start = Instant::now();
+    let response = next.run(request);
+    let elapsed = start.elapsed().as_secs_f64() * 1000.0;
+    response.with_header("Server-Timing", format!("app;dur={:.3}", elapsed))
+}
+
+#[cfg(test)]
+mod test {
+    use std::fs;
+    use std::io::{BufRead, Read, Write};
+
+    use super::*;
+    use crate::http::Headers;
+
+    // Serves a single connection on a local port, and returns a client connected to it.
+    fn connect() -> (TcpStream, thread::JoinHandle<()>) {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let addr = listener.local_addr().unwrap();
+
+        let server = thread::spawn(move || {
+            let (stream, _) = listener.accept().unwrap();
+            let alive = AtomicBool::new(true);
+            let router = routes(Files::new("public", false).unwrap());
+            handle_connection(Uuid::new_v4(), stream, &alive, &router).unwrap();
+        });
+
+        (TcpStream::connect(addr).unwrap(), server)
+    }
+
+    // Reads the head of a response, returning its status line and headers.
+    fn read_head(reader: &mut impl BufRead) -> (String, Headers) {
+        let mut status_line = String::new();
+        reader.read_line(&mut status_line).unwrap();
+
+        let mut headers = Headers::new();
+        loop {
+            let mut line = String::new();
+            reader.read_line(&mut line).unwrap();
+            let line = line.trim_end();
+            if line.is_empty() {
+                break;
+            }
+            let (name, value) = line.split_at(line.find(':').unwrap());
+            headers.append(name, value[1..].trim());
+        }
+
+        (status_line.trim_end().to_string(), headers)
+    }
+
+    // Reads a response, returning its status line, headers and body.
+    fn read_response(reader: &mut impl BufRead) -> (String, Headers, Vec<u8>) {
+        let (status_line, headers) = read_head(reader);
+
+        // Only a 304 is sent without a Content-Length.
+        let length = headers
+            .get("Content-Length")
+            .map_or(0, |l| l.parse().unwrap());
+        let mut body = vec![0; length];
+        reader.read_exact(&mut body).unwrap();
+
+        (status_line, headers, body)
+    }
+
+    // Sends a single request, and reads its response.
+    fn exchange(request: &str) -> (String, Headers, Vec<u8>) {
+        let (mut client, server) = connect();
+        client.write_all(request.as_bytes()).unwrap();
+
+        let mut reader = BufReader::new(client);
+        let response = read_response(&mut reader);
+        server.join().unwrap();
+        response
+    }
+
+    fn get(headers: &str) -> String {
+        format!(
+            "GET /index.html HTTP/1.1\r\nHost: x\r\nConnection: close\r\n{}\r\n",
+            headers
+        )
+    }
+
+    #[test]
+    fn deadline_limits_the_whole_read() {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let mut client = TcpStream::connect(listener.local_addr().unwrap()).unwrap();
+        let (stream, _) = listener.accept().unwrap();
+
+        // Each byte arrives well within the deadline of the previous one, but not all of them
+        // within the deadline of the whole read.
+        let trickle = thread::spawn(move || {
+            for _ in 0..10 {
+                if client.write_all(b"x").is_err() {
+                    break;
+                }
+                thread::sleep(Duration::from_millis(100));
+            }
+        });
+
+        let start = Instant::now();
+        let mut reader = DeadlineReader {
+            stream,
+            deadline: start + Duration::from_millis(350),
+        };
+        let err = reader.read_to_end(&mut Vec::new()).unwrap_err();
+        assert!(matches!(
+            err.kind(),
+            ErrorKind::WouldBlock | ErrorKind::TimedOut
+        ));
+        assert!(start.elapsed() < Duration::from_millis(900));
+
+        drop(reader);
+        trickle.join().unwrap();
+    }
+
+    #[test]
+    fn answers_pipelined_requests_in_order() {
+        let (mut client, server) = connect();
+
+        client
+            .write_all(
+                b"GET / HTTP/1.1\r\nHost: x\r\n\r\n\
+                  GET /nope HTTP/1.1\r\nHost: x\r\n\r\n\
+                  GET / HTTP/1.1\r\nHost: x\r\nConnection: close\r\n\r\n",
+            )
+            .unwrap();
+
+        let mut reader = BufReader::new(client);
+
+        let (status, headers, body) = read_response(&mut reader);
+        assert_eq!(status, "HTTP/1.1 200 OK");
+        assert_eq!(headers.get("Connection"), Some("keep-alive"));
+        assert_eq!(body, fs::read("public/index.html").unwrap());
+
+        let (status, headers, body) = read_response(&mut reader);
+        assert_eq!(status, "HTTP/1.1 404 Not Found");
+        assert_eq!(headers.get("Connection"), Some("keep-alive"));
+        assert_eq!(body, fs::read("public/404.html").unwrap());
+
+        let (status, headers, _) = read_response(&mut reader);
+        assert_eq!(status, "HTTP/1.1 200 OK");
+        assert_eq!(headers.get("Connection"), Some("close"));
+
+        // The server closed the connection.
+        server.join().unwrap();
+        assert_eq!(reader.read(&mut [0; 1]).unwrap(), 0);
+    }
+
+    #[test]
+    fn closes_http_10_connections_unless_asked_to_keep_them() {
+        let (mut client, server) = connect();
+
+        client
+            .write_all(
+                b"GET / HTTP/1.0\r\nConnection: Keep-Alive\r\n\r\n\
+                  GET / HTTP/1.0\r\n\r\n\
+                  GET / HTTP/1.0\r\n\r\n",
+            )
+            .unwrap();
+
+        let mut reader = BufReader::new(client);
+
+        let (_, headers, _) = read_response(&mut reader);
+        assert_eq!(headers.get("Connection"), Some("keep-alive"));
+
+        let (_, headers, _) = read_response(&mut reader);
+        assert_eq!(headers.get("Connection"), Some("close"));
+
+        // The third request was never answered.
+        server.join().unwrap();
+        assert_eq!(reader.read(&mut [0; 1]).unwrap(), 0);
+    }
+
+    #[test]
+    fn closes_connections_after_the_request_cap() {
+        let (mut client, server) = connect();
+
+        let request = b"GET / HTTP/1.1\r\nHost: x\r\n\r\n";
+        client.write_all(&request.repeat(MAX_REQUESTS + 1)).unwrap();
+
+        let mut reader = BufReader::new(client);
+        for served in 1..=MAX_REQUESTS {
+            let (_, headers, _) = read_response(&mut reader);
+            let expected = if served < MAX_REQUESTS {
+                "keep-alive"
+            } else {
+                "close"
+            };
+            assert_eq!(headers.get("Connection"), Some(expected));
+        }
+
+        server.join().unwrap();
+        assert_eq!(reader.read(&mut [0; 1]).unwrap(), 0);
+    }
+
+    #[test]
+    fn closes_connections_after_errors() {
+        let (mut client, server) = connect();
+
+        client
+            .write_all(b"GET / HTTP/1.1\r\n\r\nGET / HTTP/1.1\r\nHost: x\r\n\r\n")
+            .unwrap();
+
+        let mut reader = BufReader::new(client);
+        let (status, headers, _) = read_response(&mut reader);
+        assert_eq!(status, "HTTP/1.1 400 Bad Request");
+        assert_eq!(headers.get("Connection"), Some("close"));
+
+        server.join().unwrap();
+        assert_eq!(reader.read(&mut [0; 1]).unwrap(), 0);
+    }
+
+    #[test]
+    fn answers_conditional_requests_with_not_modified() {
+        let (status, headers, _) = exchange(&get(""));
+        assert_eq!(status, "HTTP/1.1 200 OK");
+        let etag = headers.get("ETag").unwrap();
+        let last_modified = headers.get("Last-Modified").unwrap();
+
+        let (status, not_modified, body) = exchange(&get(&format!("If-None-Match: {}\r\n", etag)));
+        assert_eq!(status, "HTTP/1.1 304 Not Modified");
+        assert_eq!(not_modified.get("ETag"), Some(etag));
+        assert_eq!(not_modified.get("Last-Modified"), Some(last_modified));
+        assert_eq!(not_modified.get("Content-Length"), None);
+        assert!(body.is_empty());
+
+        let matching = [
+            format!("If-None-Match: \"other\", W/{}\r\n", etag),
+            String::from("If-None-Match: *\r\n"),
+            format!("If-Modified-Since: {}\r\n", last_modified),
+            String::from("If-Modified-Since: Fri, 31 Dec 9999 23:59:59 GMT\r\n"),
+        ];
+        for request in matching.iter() {
+            let (status, _, _) = exchange(&get(request));
+            assert_eq!(status, "HTTP/1.1 304 Not Modified", "{}", request);
+        }
+
+        let modified = [
+            String::from("If-None-Match: \"other\"\r\n"),
+            String::from("If-Modified-Since: Thu, 01 Jan 1970 00:00:00 GMT\r\n"),
+            String::from("If-Modified-Since: yesterday\r\n"),
+            // If-None-Match takes precedence over If-Modified-Since.
+            format!(
+                "If-None-Match: \"other\"\r\nIf-Modified-Since: {}\r\n",
+                last_modified
+            ),
+        ];
+        for request in modified.iter() {
+            let (status, _, _) = exchange(&get(request));
+            assert_eq!(status, "HTTP/1.1 200 OK", "{}", request);
+        }
+    }
+
+    #[test]
+    fn answers_head_requests_without_a_body() {
+        let (mut client, server) = connect();
+
+        client
+            .write_all(
+                b"HEAD / HTTP/1.1\r\nHost: x\r\n\r\n\
+                  HEAD /nope HTTP/1.1\r\nHost: x\r\n\r\n\
+                  GET / HTTP/1.1\r\nHost: x\r\nConnection: close\r\n\r\n",
+            )
+            .unwrap();
+
+        let mut reader = BufReader::new(client);
+        let index = fs::read("public/index.html").unwrap();
+
+        let (status, headers) = read_head(&mut reader);
+        assert_eq!(status, "HTTP/1.1 200 OK");
+        assert_eq!(
+            headers.get("Content-Length"),
+            Some(index.len().to_string().as_str())
+        );
+        assert!(headers.get("ETag").is_some());
+
+        let (status, _) = read_head(&mut reader);
+        assert_eq!(status, "HTTP/1.1 404 Not Found");
+
+        // The next response follows immediately.
+        let (status, _, body) = read_response(&mut reader);
+        assert_eq!(status, "HTTP/1.1 200 OK");
+        assert_eq!(body, index);
+
+        server.join().unwrap();
+    }
+
+    #[test]
+    fn answers_single_ranges_with_partial_content() {
+        let index = fs::read("public/index.html").unwrap();
+        let len = index.len();
+
+        let (status, headers, body) = exchange(&get("Range: bytes=0-9\r\n"));
+        assert_eq!(status, "HTTP/1.1 206 Partial Content");
+        assert_eq!(
+            headers.get("Content-Range"),
+            Some(format!("bytes 0-9/{}", len).as_str())
+        );
+        assert_eq!(
+            headers.get("Content-Type"),
+            Some("text/html; charset=utf-8")
+        );
+        assert_eq!(body, &index[..10]);
+
+        let (status, _, body) = exchange(&get("Range: bytes=-5\r\n"));
+        assert_eq!(status, "HTTP/1.1 206 Partial Content");
+        assert_eq!(body, &index[len - 5..]);
+
+        let (status, headers, body) = exchange(&get("Range: bytes=100-\r\n"));
+        assert_eq!(status, "HTTP/1.1 206 Partial Content");
+        assert_eq!(
+            headers.get("Content-Range"),
+            Some(format!("bytes 100-{}/{}", len - 1, len).as_str())
+        );
+        assert_eq!(body, &index[100..]);
+
+        let (status, headers, _) = exchange(&get(&format!("Range: bytes={}-\r\n", len)));
+        assert_eq!(status, "HTTP/1.1 416 Range Not Satisfiable");
+        assert_eq!(
+            headers.get("Content-Range"),
+            Some(format!("bytes */{}", len).as_str())
+        );
+
+        // Invalid ranges are ignored.
+        let (status, _, body) = exchange(&get("Range: bytes=9-0\r\n"));
+        assert_eq!(status, "HTTP/1.1 200 OK");
+        assert_eq!(body, index);
+    }
+
+    #[test]
+    fn answers_multiple_ranges_with_multipart_byteranges() {
+        let index = fs::read("public/index.html").unwrap();
+        let len = index.len();
+
+        let (status, headers, body) = exchange(&get("Range: bytes=0-4, -3\r\n"));
+        assert_eq!(status, "HTTP/1.1 206 Partial Content");
+        assert_eq!(headers.get("Content-Range"), None);
+
+        let content_type = headers.get("Content-Type").unwrap();
+        let boundary = content_type
+            .strip_prefix("multipart/byteranges; boundary=")
+            .unwrap();
+
+        let mut expected = format!(
+            "--{0}\r\n\
+             Content-Type: text/html; charset=utf-8\r\n\
+             Content-Range: bytes 0-4/{1}\r\n\r\n",
+            boundary, len
+        )
+        .into_bytes();
+        expected.extend_from_slice(&index[..5]);
+        expected.extend_from_slice(
+            format!(
+                "\r\n--{0}\r\n\
+                 Content-Type: text/html; charset=utf-8\r\n\
+                 Content-Range: bytes {1}-{2}/{3}\r\n\r\n",
+                boundary,
+                len - 3,
+                len - 1,
+                len
+            )
+            .as_bytes(),
+        );
+        expected.extend_from_slice(&index[len - 3..]);
+        expected.extend_from_slice(format!("\r\n--{}--\r\n", boundary).as_bytes());
+
+        assert_eq!(body, expected);
+    }
+
+    #[test]
+    fn honors_ranges_only_if_the_representation_is_unchanged() {
+        let (_, headers, _) = exchange(&get(""));
+        let etag = headers.get("ETag").unwrap();
+        let last_modified = headers.get("Last-Modified").unwrap();
+
+        for validator in [etag, last_modified].iter() {
+            let request = format!("Range: bytes=0-0\r\nIf-Range: {}\r\n", validator);
+            let (status, _, body) = exchange(&get(&request));
+            assert_eq!(status, "HTTP/1.1 206 Partial Content");
+            assert_eq!(body, b"<");
+        }
+
+        for validator in ["\"other\"", "Thu, 01 Jan 1970 00:00:00 GMT"].iter() {
+            let request = format!("Range: bytes=0-0\r\nIf-Range: {}\r\n", validator);
+            let (status, _, body) = exchange(&get(&request));
+            assert_eq!(status, "HTTP/1.1 200 OK");
+            assert_eq!(body, fs::read("public/index.html").unwrap());
+        }
+
+        // Ranges are ignored for HEAD.
+        let (mut client, server) = connect();
+        client
+            .write_all(
+                b"HEAD / HTTP/1.1\r\nHost: x\r\nRange: bytes=0-0\r\nConnection: close\r\n\r\n",
+            )
+            .unwrap();
+        let (status, _) = read_head(&mut BufReader::new(client));
+        assert_eq!(status, "HTTP/1.1 200 OK");
+        server.join().unwrap();
+    }
+
+    #[test]
+    fn routes_requests_and_rejects_other_methods() {
+        let (mut client, server) = connect();
+
+        client
+            .write_all(
+                b"POST / HTTP/1.1\r\nHost: x\r\nContent-Length: 2\r\n\r\nhi\
+                  DELETE /sleep HTTP/1.1\r\nHost: x\r\n\r\n\
+                  GET /hello/world HTTP/1.1\r\nHost: x\r\n\r\n\
+                  GET /hello/a%20b HTTP/1.1\r\nHost: x\r\n\r\n\
+                  GET / HTTP/1.1\r\nHost: x\r\nConnection: close\r\n\r\n",
+            )
+            .unwrap();
+
+        let mut reader = BufReader::new(client);
+
+        for _ in 0..2 {
+            let (status, headers, _) = read_response(&mut reader);
+            assert_eq!(status, "HTTP/1.1 405 Method Not Allowed");
+            assert_eq!(headers.get("Allow"), Some("GET, HEAD"));
+            assert_eq!(headers.get("Connection"), Some("keep-alive"));
+        }
+
+        let (status, _, body) = read_response(&mut reader);
+        assert_eq!(status, "HTTP/1.1 200 OK");
+        assert_eq!(body, b"Hello, world!\n");
+
+        // Parameters are percent-decoded.
+        let (status, _, body) = read_response(&mut reader);
+        assert_eq!(status, "HTTP/1.1 200 OK");
+        assert_eq!(body, b"Hello, a b!\n");
+
+        let (status, headers, _) = read_response(&mut reader);
+        assert_eq!(status, "HTTP/1.1 200 OK");
+        assert!(headers
+            .get("Server-Timing")
+            .unwrap()
+            .starts_with("app;dur="));
+
+        server.join().unwrap();
+    }
+}
@@ -0,0 +1,599 @@
+//! HTTP/1.x request parsing.
+
+use std::error::Error;
+use std::fmt;
+use std::io::{self, prelude::*};
+
+use crate::http::{Headers, Status, Version};
+
+pub struct Request {
+    pub method: String,
+    pub target: String,
+    pub version: Version,
+    pub headers: Headers,
+
+    // Not used by any of the current endpoints.
+    #[allow(dead_code)]
+    pub body: Vec<u8>,
+}
+
+/// Bounds on the size of the requests that will be accepted.
+#[derive(Clone, Debug)]
+pub struct Limits {
+    /// Maximum size of the request line and of each header field, in bytes.
+    pub max_line: usize,
+
+    /// Maximum size of the whole header section (request line included), in bytes.
+    pub max_header_section: usize,
+
+    /// Maximum number of header fields.
+    pub max_headers: usize,
+
+    /// Maximum size of the (decoded) body, in bytes.
+    pub max_body: usize,
+}
+
+impl Default for Limits {
+    fn default() -> Limits {
+        Limits {
+            max_line: 8 * 1024,
+            max_header_section: 64 * 1024,
+            max_headers: 100,
+            max_body: 1024 * 1024,
+        }
+    }
+}
+
+#[derive(Debug)]
+pub enum ParseError {
+    /// The request is malformed.
+    BadRequest(&'static str),
+
+    /// The body is larger than `Limits::max_body`.
+    PayloadTooLarge,
+
+    /// The request line or the header section are larger than the limits.
+    HeadersTooLarge,
+
+    /// The body uses a transfer coding other than chunked.
+    NotImplemented,
+
+    /// The request is for a HTTP version other than 1.0 or 1.1.
+    VersionNotSupported,
+
+    /// The connection failed or was closed in the middle of the request.
+    Io(io::Error),
+}
+
+impl ParseError {
+    /// Returns the status of the response for this error, if one can still be sent.
+    pub fn status(&self) -> Option<Status> {
+        match self {
+            ParseError::BadRequest(_) => Some(Status::BAD_REQUEST),
+            ParseError::PayloadTooLarge => Some(Status::PAYLOAD_TOO_LARGE),
+            ParseError::HeadersTooLarge => Some(Status::HEADER_FIELDS_TOO_LARGE),
+            ParseError::NotImplemented => Some(Status::NOT_IMPLEMENTED),
+            ParseError::VersionNotSupported => Some(Status::VERSION_NOT_SUPPORTED),
+            ParseError::Io(_) => None,
+        }
+    }
+}
+
+impl fmt::Display for ParseError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ParseError::BadRequest(reason) => write!(f, "bad request: {}", reason),
+            ParseError::PayloadTooLarge => write!(f, "payload too large"),
+            ParseError::HeadersTooLarge => write!(f, "request header fields too large"),
+            ParseError::NotImplemented => write!(f, "unsupported transfer coding"),
+            ParseError::VersionNotSupported => write!(f, "HTTP version not supported"),
+            ParseError::Io(err) => write!(f, "i/o error: {}", err),
+        }
+    }
+}
+
+impl Error for ParseError {
+    fn source(&self) -> Option<&(dyn Error + 'static)> {
+        match self {
+            ParseError::Io(err) => Some(err),
+            _ => None,
+        }
+    }
+}
+
+impl From<io::Error> for ParseError {
+    fn from(err: io::Error) -> ParseError {
+        ParseError::Io(err)
+    }
+}
+
+type Result<T> = std::result::Result<T, ParseError>;
+
+impl Request {
+    /// Reads the next request from `reader`, or `None` if the connection was closed before it
+    /// started.
+    pub fn read_from(reader: &mut impl BufRead, limits: &Limits) -> Result<Option<Request>> {
+        let mut section = limits.max_header_section;
+
+        // Be lenient and skip empty lines before the request line (RFC 7230, section 3.5).
+        let request_line = loop {
+            match read_line(reader, limits.max_line, &mut section)? {
+                None => return Ok(None),
+                Some(line) if line.is_empty() => continue,
+                Some(line) => break line,
+            }
+        };
+
+        let mut parts = request_line.split(' ');
+        let (method, target, version) = match (parts.next(), parts.next(), parts.next()) {
+            (Some(m), Some(t), Some(v)) if parts.next().is_none() => (m, t, v),
+            _ => return Err(ParseError::BadRequest("malformed request line")),
+        };
+
+        if !is_token(method) {
+            return Err(ParseError::BadRequest("invalid method"));
+        }
+
+        if target.is_empty() || target.bytes().any(|b| b.is_ascii_control()) {
+            return Err(ParseError::BadRequest("invalid request target"));
+        }
+
+        let version = parse_version(version)?;
+
+        let mut headers = Headers::new();
+        loop {
+            let line = read_line(reader, limits.max_line, &mut section)?
+                .ok_or(ParseError::BadRequest("incomplete header section"))?;
+
+            if line.is_empty() {
+                break;
+            }
+
+            if headers.len() == limits.max_headers {
+                return Err(ParseError::HeadersTooLarge);
+            }
+
+            let (name, value) = parse_field(&line)?;
+            headers.append(name, value);
+        }
+
+        if version == Version::Http11 && headers.get_all("Host").count() != 1 {
+            return Err(ParseError::BadRequest("missing or repeated Host"));
+        }
+
+        let body = read_body(reader, &headers, limits)?;
+
+        Ok(Some(Request {
+            method: method.to_string(),
+            target: target.to_string(),
+            version,
+            headers,
+            body,
+        }))
+    }
+}
+
+impl Request {
+    /// Returns whether the client wants to keep the connection open after this request: by
+    /// default in HTTP/1.1, and only if asked with `Connection: keep-alive` in HTTP/1.0.
+    pub fn keep_alive(&self) -> bool {
+        match self.version {
+            Version::Http11 => !self.headers.has_token("Connection", "close"),
+            Version::Http10 => self.headers.has_token("Connection", "keep-alive"),
+        }
+    }
+}
+
+impl fmt::Display for Request {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{} {} {}", self.method, self.target, self.version)
+    }
+}
+
+// Reads a line, without the trailing CRLF (or LF), charging its size to the remaining `section`;
+// returns `None` if the connection was closed before the line started.
+fn read_line(
+    reader: &mut impl BufRead,
+    max_line: usize,
+    section: &mut usize,
+) -> Result<Option<String>> {
+    let limit = max_line.min(*section);
+
+    let mut line = Vec::new();
+    reader.take(limit as u64 + 1).read_until(b'\n', &mut line)?;
+
+    if line.is_empty() {
+        return Ok(None);
+    }
+
+    if line.len() > limit {
+        return Err(ParseError::HeadersTooLarge);
+    }
+
+    if line.last() != Some(&b'\n') {
+        return Err(io::Error::from(io::ErrorKind::UnexpectedEof).into());
+    }
+
+    *section -= line.len().min(*section);
+
+    line.pop();
+    if line.last() == Some(&b'\r') {
+        line.pop();
+    }
+
+    String::from_utf8(line)
+        .map(Some)
+        .map_err(|_| ParseError::BadRequest("invalid characters"))
+}
+
+fn parse_version(version: &str) -> Result<Version> {
+    match version {
+        "HTTP/1.1" => Ok(Version::Http11),
+        "HTTP/1.0" => Ok(Version::Http10),
+        _ => {
+            let digits = version.strip_prefix("HTTP/").map(|v| v.as_bytes());
+            match digits {
+                Some([major, b'.', minor]) if major.is_ascii_digit() && minor.is_ascii_digit() => {
+                    Err(ParseError::VersionNotSupported)
+                }
+                _ => Err(ParseError::BadRequest("invalid HTTP version")),
+            }
+        }
+    }
+}
+
+fn parse_field(line: &str) -> Result<(&str, &str)> {
+    if line.starts_with(' ') || line.starts_with('\t') {
+        return Err(ParseError::BadRequest("obsolete line folding"));
+    }
+
+    let colon = line
+        .find(':')
+        .ok_or(ParseError::BadRequest("malformed header field"))?;
+    let (name, value) = (&line[..colon], &line[colon + 1..]);
+
+    // Also rejects whitespace between the name and the colon (RFC 7230, section 3.2.4).
+    if !is_token(name) {
+        return Err(ParseError::BadRequest("invalid header field name"));
+    }
+
+    Ok((name, value.trim_matches(|c| c == ' ' || c == '\t')))
+}
+
+fn read_body(reader: &mut impl BufRead, headers: &Headers, limits: &Limits) -> Result<Vec<u8>> {
+    let transfer_encoding = headers.get_all("Transfer-Encoding").last();
+    let content_lengths: Vec<&str> = headers
+        .get_all("Content-Length")
+        .flat_map(|v| v.split(','))
+        .map(str::trim)
+        .collect();
+
+    if let Some(encoding) = transfer_encoding {
+        // Both framings could be used to smuggle requests (RFC 7230, section 3.3.3).
+        if !content_lengths.is_empty() {
+            return Err(ParseError::BadRequest(
+                "both Transfer-Encoding and Content-Length",
+            ));
+        }
+
+        let last = encoding.rsplit(',').next().unwrap().trim();
+        if !last.eq_ignore_ascii_case("chunked") {
+            return Err(ParseError::BadRequest("body length cannot be determined"));
+        }
+        if encoding.contains(',') || headers.get_all("Transfer-Encoding").count() > 1 {
+            return Err(ParseError::NotImplemented);
+        }
+
+        return read_chunked(reader, limits);
+    }
+
+    let length = match content_lengths.split_first() {
+        None => return Ok(Vec::new()),
+        Some((first, rest)) => {
+            if rest.iter().any(|other| other != first) {
+                return Err(ParseError::BadRequest("conflicting Content-Length"));
+            }
+            if first.is_empty() || !first.bytes().all(|b| b.is_ascii_digit()) {
+                return Err(ParseError::BadRequest("invalid Content-Length"));
+            }
+            first.parse::<usize>().unwrap_or(usize::MAX)
+        }
+    };
+
+    if length > limits.max_body {
+        return Err(ParseError::PayloadTooLarge);
+    }
+
+    let mut body = vec![0; length];
+    reader.read_exact(&mut body)?;
+    Ok(body)
+}
+
+fn read_chunked(reader: &mut impl BufRead, limits: &Limits) -> Result<Vec<u8>> {
+    let mut body = Vec::new();
+
+    // Chunk size lines and trailers are bounded like the header section.
+    let mut section = limits.max_header_section;
+
+    loop {
+        let line = read_line(reader, limits.max_line, &mut section)?
+            .ok_or(ParseError::BadRequest("incomplete chunked body"))?;
+
+        // Ignore any chunk extensions.
+        let size = line.split(';').next().unwrap().trim();
+        if size.is_empty() || !size.bytes().all(|b| b.is_ascii_hexdigit()) {
+            return Err(ParseError::BadRequest("invalid chunk size"));
+        }
+        let size = usize::from_str_radix(size, 16).unwrap_or(usize::MAX);
+
+        if size == 0 {
+            break;
+        }
+
+        if size > limits.max_body - body.len() {
+            return Err(ParseError::PayloadTooLarge);
+        }
+
+        let start = body.len();
+        body.resize(start + size, 0);
+        reader.read_exact(&mut body[start..])?;
+
+        let mut crlf = [0; 2];
+        reader.read_exact(&mut crlf)?;
+        if &crlf != b"\r\n" {
+            return Err(ParseError::BadRequest("missing CRLF after chunk"));
+        }
+    }
+
+    // Discard any trailer fields.
+    loop {
+        let line = read_line(reader, limits.max_line, &mut section)?
+            .ok_or(ParseError::BadRequest("incomplete chunked body"))?;
+        if line.is_empty() {
+            break;
+        }
+    }
+
+    Ok(body)
+}
+
+// Whether `s` is a token (RFC 7230, section 3.2.6), as required for methods and field names.
+fn is_token(s: &str) -> bool {
+    !s.is_empty()
+        && s.bytes()
+            .all(|b| b.is_ascii_alphanumeric() || b"!#$%&'*+-.^_`|~".contains(&b))
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn parse(bytes: &[u8]) -> Result<Option<Request>> {
+        Request::read_from(&mut &bytes[..], &Limits::default())
+    }
+
+    fn parse_err(bytes: &[u8]) -> Option<Status> {
+        match parse(bytes) {
+            Ok(_) => panic!("unexpectedly parsed {:?}", String::from_utf8_lossy(bytes)),
+            Err(err) => err.status(),
+        }
+    }
+
+    #[test]
+    fn parses_request_line_and_headers() {
+        let request = parse(
+            b"GET /index.html?q=1 HTTP/1.1\r\n\
+              Host: example.com\r\nAccept:  text/html \r\nX-Empty:\r\n\r\n",
+        )
+        .unwrap()
+        .unwrap();
+
+        assert_eq!(request.method, "GET");
+        assert_eq!(request.target, "/index.html?q=1");
+        assert_eq!(request.version, Version::Http11);
+        assert_eq!(request.headers.get("host"), Some("example.com"));
+        assert_eq!(request.headers.get("ACCEPT"), Some("text/html"));
+        assert_eq!(request.headers.get("x-empty"), Some(""));
+        assert!(request.body.is_empty());
+        assert_eq!(request.to_string(), "GET /index.html?q=1 HTTP/1.1");
+    }
+
+    #[test]
+    fn accepts_http_10_and_bare_lf() {
+        let request = parse(b"\r\nGET / HTTP/1.0\nUser-Agent: test\n\n")
+            .unwrap()
+            .unwrap();
+
+        assert_eq!(request.version, Version::Http10);
+        assert_eq!(request.headers.get("user-agent"), Some("test"));
+    }
+
+    #[test]
+    fn decides_whether_to_keep_alive() {
+        let keep_alive = |bytes: &[u8]| parse(bytes).unwrap().unwrap().keep_alive();
+
+        assert!(keep_alive(b"GET / HTTP/1.1\r\nHost: x\r\n\r\n"));
+        assert!(!keep_alive(
+            b"GET / HTTP/1.1\r\nHost: x\r\nConnection: close\r\n\r\n"
+        ));
+        assert!(!keep_alive(
+            b"GET / HTTP/1.1\r\nHost: x\r\nConnection: Upgrade, Close\r\n\r\n"
+        ));
+        assert!(!keep_alive(b"GET / HTTP/1.0\r\n\r\n"));
+        assert!(keep_alive(
+            b"GET / HTTP/1.0\r\nConnection: Keep-Alive\r\n\r\n"
+        ));
+    }
+
+    #[test]
+    fn returns_none_on_closed_connection() {
+        assert!(parse(b"").unwrap().is_none());
+        assert!(parse(b"\r\n").unwrap().is_none());
+    }
+
+    #[test]
+    fn reads_sequential_requests() {
+        let mut bytes = &b"POST /a HTTP/1.1\r\nHost: x\r\nContent-Length: 3\r\n\r\nabc\
+                           GET /b HTTP/1.1\r\nHost: x\r\n\r\n"[..];
+
+        let first = Request::read_from(&mut bytes, &Limits::default())
+            .unwrap()
+            .unwrap();
+        let second = Request::read_from(&mut bytes, &Limits::default())
+            .unwrap()
+            .unwrap();
+
+        assert_eq!(
+            (first.target.as_str(), first.body.as_slice()),
+            ("/a", &b"abc"[..])
+        );
+        assert_eq!(second.target, "/b");
+        assert!(Request::read_from(&mut bytes, &Limits::default())
+            .unwrap()
+            .is_none());
+    }
+
+    #[test]
+    fn reads_chunked_bodies() {
+        let request = parse(
+            b"POST / HTTP/1.1\r\nHost: x\r\nTransfer-Encoding: chunked\r\n\r\n\
+              5;ext=1\r\nhello\r\n7\r\n, world\r\n0\r\nTrailer: yes\r\n\r\n",
+        )
+        .unwrap()
+        .unwrap();
+
+        assert_eq!(request.body, b"hello, world");
+    }
+
+    #[test]
+    fn rejects_malformed_requests() {
+        let bad = Some(Status::BAD_REQUEST);
+
+        assert_eq!(parse_err(b"GET /\r\n\r\n"), bad);
+        assert_eq!(parse_err(b"GET  / HTTP/1.1\r\nHost: x\r\n\r\n"), bad);
+        assert_eq!(parse_err(b"G(T / HTTP/1.1\r\nHost: x\r\n\r\n"), bad);
+        assert_eq!(parse_err(b"GET / HTTX/1.1\r\nHost: x\r\n\r\n"), bad);
+        assert_eq!(parse_err(b"GET / HTTP/1.1\r\n\r\n"), bad);
+        assert_eq!(
+            parse_err(b"GET / HTTP/1.1\r\nHost: x\r\nHost: y\r\n\r\n"),
+            bad
+        );
+        assert_eq!(parse_err(b"GET / HTTP/1.1\r\nHost : x\r\n\r\n"), bad);
+        assert_eq!(
+            parse_err(b"GET / HTTP/1.1\r\nHost: x\r\n folded\r\n\r\n"),
+            bad
+        );
+        assert_eq!(
+            parse_err(b"GET / HTTP/1.1\r\nHost: x\r\nNoColon\r\n\r\n"),
+            bad
+        );
+    }
+
+    #[test]
+    fn rejects_ambiguous_bodies() {
+        let bad = Some(Status::BAD_REQUEST);
+
+        assert_eq!(
+            parse_err(
+                b"POST / HTTP/1.1\r\nHost: x\r\nContent-Length: 1\r\nContent-Length: 2\r\n\r\nab"
+            ),
+            bad
+        );
+        assert_eq!(
+            parse_err(b"POST / HTTP/1.1\r\nHost: x\r\nContent-Length: -1\r\n\r\n"),
+            bad
+        );
+        assert_eq!(
+            parse_err(
+                b"POST / HTTP/1.1\r\nHost: x\r\nContent-Length: 1\r\n\
+                  Transfer-Encoding: chunked\r\n\r\n0\r\n\r\n"
+            ),
+            bad
+        );
+        assert_eq!(
+            parse_err(b"POST / HTTP/1.1\r\nHost: x\r\nTransfer-Encoding: gzip\r\n\r\n"),
+            bad
+        );
+        assert_eq!(
+            parse_err(
+                b"POST / HTTP/1.1\r\nHost: x\r\nTransfer-Encoding: gzip, chunked\r\n\r\n0\r\n\r\n"
+            ),
+            Some(Status::NOT_IMPLEMENTED)
+        );
+        assert_eq!(
+            parse_err(b"POST / HTTP/1.1\r\nHost: x\r\nTransfer-Encoding: chunked\r\n\r\nzz\r\n"),
+            bad
+        );
+
+        // Repeated but equal lengths are fine.
+        let request = parse(b"POST / HTTP/1.1\r\nHost: x\r\nContent-Length: 2, 2\r\n\r\nab")
+            .unwrap()
+            .unwrap();
+        assert_eq!(request.body, b"ab");
+    }
+
+    #[test]
+    fn rejects_unsupported_versions() {
+        assert_eq!(
+            parse_err(b"GET / HTTP/2.0\r\nHost: x\r\n\r\n"),
+            Some(Status::VERSION_NOT_SUPPORTED)
+        );
+        assert_eq!(
+            parse_err(b"GET / HTTP/0.9\r\n\r\n"),
+            Some(Status::VERSION_NOT_SUPPORTED)
+        );
+    }
+
+    #[test]
+    fn enforces_limits() {
+        let limits = Limits {
+            max_line: 64,
+            max_header_section: 128,
+            max_headers: 3,
+            max_body: 8,
+        };
+        let parse = |bytes: &[u8]| {
+            Request::read_from(&mut &bytes[..], &limits)
+                .err()
+                .and_then(|err| err.status())
+        };
+
+        let long_target = format!("GET /{} HTTP/1.1\r\nHost: x\r\n\r\n", "a".repeat(64));
+        assert_eq!(
+            parse(long_target.as_bytes()),
+            Some(Status::HEADER_FIELDS_TOO_LARGE)
+        );
+
+        let many = b"GET / HTTP/1.1\r\nHost: x\r\nA: 1\r\nB: 2\r\nC: 3\r\n\r\n";
+        assert_eq!(parse(many), Some(Status::HEADER_FIELDS_TOO_LARGE));
+
+        let large_section = format!(
+            "GET / HTTP/1.1\r\nHost: x\r\nA: {}\r\nB: {}\r\n\r\n",
+            "a".repeat(50),
+            "b".repeat(50)
+        );
+        assert_eq!(
+            parse(large_section.as_bytes()),
+            Some(Status::HEADER_FIELDS_TOO_LARGE)
+        );
+
+        let large_body = b"POST / HTTP/1.1\r\nHost: x\r\nContent-Length: 9\r\n\r\n123456789";
+        assert_eq!(parse(large_body), Some(Status::PAYLOAD_TOO_LARGE));
+
+        let large_chunks = b"POST / HTTP/1.1\r\nHost: x\r\nTransfer-Encoding: chunked\r\n\r\n\
+                             5\r\n12345\r\n5\r\n12345\r\n0\r\n\r\n";
+        assert_eq!(parse(large_chunks), Some(Status::PAYLOAD_TOO_LARGE));
+    }
+
+    #[test]
+    fn truncated_requests_are_io_errors() {
+        assert!(matches!(
+            parse(b"GET / HTTP/1.1\r\nHost: x"),
+            Err(ParseError::Io(_))
+        ));
+        assert!(matches!(
+            parse(b"POST / HTTP/1.1\r\nHost: x\r\nContent-Length: 5\r\n\r\nab"),
+            Err(ParseError::Io(_))
+        ));
+    }
+}
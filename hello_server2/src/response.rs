@@ -0,0 +1,180 @@
+//! HTTP/1.1 responses.
+
+use std::fs::File;
+use std::io::{self, prelude::*, SeekFrom};
+
+use crate::http::{Headers, Status};
+
+pub struct Response {
+    pub status: Status,
+    pub headers: Headers,
+    pub body: Body,
+}
+
+pub enum Body {
+    Bytes(Vec<u8>),
+
+    /// The first `len` bytes of a file (from its current position), which are streamed instead of
+    /// read into memory.
+    File(File, u64),
+
+    /// Ranges of a file, each preceded by some bytes (e.g. the headers of its part in a multipart
+    /// body), and followed by a trailer.
+    Ranges(File, Vec<Part>, Vec<u8>),
+}
+
+pub struct Part {
+    pub head: Vec<u8>,
+    pub start: u64,
+    pub len: u64,
+}
+
+impl Body {
+    pub fn len(&self) -> u64 {
+        match self {
+            Body::Bytes(bytes) => bytes.len() as u64,
+            Body::File(_, len) => *len,
+            Body::Ranges(_, parts, trailer) => {
+                let parts: u64 = parts.iter().map(|p| p.head.len() as u64 + p.len).sum();
+                parts + trailer.len() as u64
+            }
+        }
+    }
+
+    fn write_to(&self, writer: &mut impl Write) -> io::Result<()> {
+        match self {
+            Body::Bytes(bytes) => writer.write_all(bytes),
+            Body::File(file, len) => copy_file(file, *len, writer),
+            Body::Ranges(file, parts, trailer) => {
+                let mut file = file;
+                for part in parts {
+                    writer.write_all(&part.head)?;
+                    file.seek(SeekFrom::Start(part.start))?;
+                    copy_file(file, part.len, writer)?;
+                }
+                writer.write_all(trailer)
+            }
+        }
+    }
+}
+
+fn copy_file(file: &File, len: u64, writer: &mut impl Write) -> io::Result<()> {
+    let copied = io::copy(&mut file.take(len), writer)?;
+    if copied < len {
+        // The file was truncated after the headers were sent.
+        return Err(io::ErrorKind::UnexpectedEof.into());
+    }
+    Ok(())
+}
+
+impl Response {
+    pub fn new(status: Status) -> Response {
+        Response {
+            status,
+            headers: Headers::new(),
+            body: Body::Bytes(Vec::new()),
+        }
+    }
+
+    pub fn with_header(mut self, name: &str, value: impl Into<String>) -> Response {
+        self.headers.set(name, value);
+        self
+    }
+
+    pub fn with_body(mut self, content_type: &str, body: impl Into<Vec<u8>>) -> Response {
+        self.body = Body::Bytes(body.into());
+        self.with_header("Content-Type", content_type)
+    }
+
+    pub fn with_file(mut self, content_type: &str, file: File, len: u64) -> Response {
+        self.body = Body::File(file, len);
+        self.with_header("Content-Type", content_type)
+    }
+
+    /// A plain text response for a status that has no content of its own, like most errors.
+    pub fn plain(status: Status) -> Response {
+        Response::new(status).with_body("text/plain; charset=utf-8", format!("{}\n", status))
+    }
+
+    /// Writes the response, always framed with `Content-Length`.
+    pub fn write_to(&self, writer: &mut impl Write) -> io::Result<()> {
+        self.write_head(writer)?;
+        self.body.write_to(writer)?;
+        writer.flush()
+    }
+
+    /// Writes the response without its body, as an answer to a `HEAD` request; `Content-Length`
+    /// is still that of the body.
+    pub fn write_head_to(&self, writer: &mut impl Write) -> io::Result<()> {
+        self.write_head(writer)?;
+        writer.flush()
+    }
+
+    fn write_head(&self, writer: &mut impl Write) -> io::Result<()> {
+        let mut head = format!("HTTP/1.1 {}\r\n", self.status);
+
+        for (name, value) in self.headers.iter() {
+            head.push_str(&format!("{}: {}\r\n", name, value));
+        }
+
+        // A 304 has no body, and a Content-Length would have to be that of the full response.
+        if self.status != Status::NOT_MODIFIED {
+            head.push_str(&format!("Content-Length: {}\r\n", self.body.len()));
+        }
+        head.push_str("\r\n");
+
+        writer.write_all(head.as_bytes())
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn writes_status_headers_and_body() {
+        let response = Response::new(Status::OK)
+            .with_header("Connection", "close")
+            .with_body("text/html", "<p>hi</p>");
+
+        let mut bytes = Vec::new();
+        response.write_to(&mut bytes).unwrap();
+
+        assert_eq!(
+            String::from_utf8(bytes).unwrap(),
+            "HTTP/1.1 200 OK\r\n\
+             Connection: close\r\n\
+             Content-Type: text/html\r\n\
+             Content-Length: 9\r\n\
+             \r\n\
+             <p>hi</p>"
+        );
+    }
+
+    #[test]
+    fn writes_only_the_head_for_head_requests() {
+        let response = Response::new(Status::OK).with_body("text/html", "<p>hi</p>");
+
+        let mut bytes = Vec::new();
+        response.write_head_to(&mut bytes).unwrap();
+
+        assert_eq!(
+            String::from_utf8(bytes).unwrap(),
+            "HTTP/1.1 200 OK\r\n\
+             Content-Type: text/html\r\n\
+             Content-Length: 9\r\n\
+             \r\n"
+        );
+
+        let mut bytes = Vec::new();
+        Response::new(Status::NOT_MODIFIED)
+            .with_header("ETag", "\"x\"")
+            .write_to(&mut bytes)
+            .unwrap();
+
+        assert_eq!(
+            String::from_utf8(bytes).unwrap(),
+            "HTTP/1.1 304 Not Modified\r\nETag: \"x\"\r\n\r\n"
+        );
+    }
+}
@@ -0,0 +1,402 @@
+//! Routing of requests to handlers, by method and path pattern.
+//!
+//! Patterns are made of literal segments, named parameters (`/users/:id`) and, at the end, an
+//! optional wildcard that captures the rest of the path (`/static/*path`).  Requests that match
+//! no pattern get a 404, and those that only match with another method get a 405 listing the
+//! allowed methods.  Middleware wraps every request, including those that end in a 404 or 405.
+
+use std::panic::RefUnwindSafe;
+
+use crate::http::{self, Status};
+use crate::request::Request;
+use crate::response::Response;
+
+type Handler = Box<dyn Fn(&Request, &Params) -> Response + Send + Sync + RefUnwindSafe>;
+type Middleware = Box<dyn Fn(&Request, Next<'_>) -> Response + Send + Sync + RefUnwindSafe>;
+type Fallback = Box<dyn Fn(&Request) -> Response + Send + Sync + RefUnwindSafe>;
+
+pub struct Router {
+    routes: Vec<Route>,
+    middleware: Vec<Middleware>,
+    not_found: Fallback,
+}
+
+struct Route {
+    method: String,
+    pattern: Pattern,
+    handler: Handler,
+}
+
+/// The parameters captured from the path of a request, percent-decoded.
+#[derive(Debug, Default, PartialEq, Eq)]
+pub struct Params {
+    values: Vec<(String, String)>,
+}
+
+impl Params {
+    pub fn get(&self, name: &str) -> Option<&str> {
+        self.values
+            .iter()
+            .find(|(n, _)| n == name)
+            .map(|(_, v)| v.as_str())
+    }
+
+    fn decode(self) -> Option<Params> {
+        let values = self
+            .values
+            .into_iter()
+            .map(|(name, value)| Some((name, http::percent_decode(&value)?)))
+            .collect::<Option<_>>()?;
+        Some(Params { values })
+    }
+}
+
+/// The rest of the chain, for a middleware to run (or not) around the request.
+pub struct Next<'a> {
+    router: &'a Router,
+    middleware: &'a [Middleware],
+}
+
+impl Next<'_> {
+    pub fn run(self, request: &Request) -> Response {
+        match self.middleware.split_first() {
+            Some((first, rest)) => first(
+                request,
+                Next {
+                    router: self.router,
+                    middleware: rest,
+                },
+            ),
+            None => self.router.dispatch(request),
+        }
+    }
+}
+
+impl Router {
+    pub fn new() -> Router {
+        Router {
+            routes: Vec::new(),
+            middleware: Vec::new(),
+            not_found: Box::new(|_| Response::plain(Status::NOT_FOUND)),
+        }
+    }
+
+    /// Adds a route; routes are tried in the order they were added.
+    ///
+    /// Panics if the pattern does not start with a slash, or has a wildcard before its end.
+    pub fn route(
+        mut self,
+        method: &str,
+        pattern: &str,
+        handler: impl Fn(&Request, &Params) -> Response + Send + Sync + RefUnwindSafe + 'static,
+    ) -> Router {
+        self.routes.push(Route {
+            method: method.to_string(),
+            pattern: Pattern::parse(pattern),
+            handler: Box::new(handler),
+        });
+        self
+    }
+
+    /// Adds a route for `GET`, which also answers `HEAD` requests.
+    pub fn get(
+        self,
+        pattern: &str,
+        handler: impl Fn(&Request, &Params) -> Response + Send + Sync + RefUnwindSafe + 'static,
+    ) -> Router {
+        self.route("GET", pattern, handler)
+    }
+
+    /// Adds a middleware, which runs inside those added before it.
+    pub fn wrap(
+        mut self,
+        middleware: impl Fn(&Request, Next<'_>) -> Response + Send + Sync + RefUnwindSafe + 'static,
+    ) -> Router {
+        self.middleware.push(Box::new(middleware));
+        self
+    }
+
+    /// Replaces the handler for requests that match no route.
+    pub fn not_found(
+        mut self,
+        handler: impl Fn(&Request) -> Response + Send + Sync + RefUnwindSafe + 'static,
+    ) -> Router {
+        self.not_found = Box::new(handler);
+        self
+    }
+
+    pub fn handle(&self, request: &Request) -> Response {
+        Next {
+            router: self,
+            middleware: &self.middleware,
+        }
+        .run(request)
+    }
+
+    fn dispatch(&self, request: &Request) -> Response {
+        let path = request.target.split('?').next().unwrap();
+        let mut allowed = Vec::new();
+
+        for route in &self.routes {
+            let params = match route.pattern.matches(path) {
+                Some(params) => params,
+                None => continue,
+            };
+
+            if route.method == request.method || (request.method == "HEAD" && route.method == "GET")
+            {
+                // Patterns are matched before decoding, so that an escaped slash (`%2F`) stays
+                // within its segment.
+                return match params.decode() {
+                    Some(params) => (route.handler)(request, &params),
+                    None => Response::plain(Status::BAD_REQUEST),
+                };
+            }
+
+            allowed.push(route.method.as_str());
+            if route.method == "GET" {
+                allowed.push("HEAD");
+            }
+        }
+
+        if allowed.is_empty() {
+            return (self.not_found)(request);
+        }
+
+        allowed.sort_unstable();
+        allowed.dedup();
+        Response::plain(Status::METHOD_NOT_ALLOWED).with_header("Allow", allowed.join(", "))
+    }
+}
+
+struct Pattern {
+    segments: Vec<Segment>,
+
+    // The name of the wildcard that captures the rest of the path, if any.
+    tail: Option<String>,
+}
+
+enum Segment {
+    Literal(String),
+    Param(String),
+}
+
+impl Pattern {
+    fn parse(pattern: &str) -> Pattern {
+        assert!(pattern.starts_with('/'), "pattern must start with '/'");
+
+        let mut segments = Vec::new();
+        let mut tail = None;
+
+        for segment in pattern[1..].split('/') {
+            assert!(tail.is_none(), "wildcard must be at the end of the pattern");
+
+            if let Some(name) = segment.strip_prefix('*') {
+                tail = Some(name.to_string());
+            } else if let Some(name) = segment.strip_prefix(':') {
+                segments.push(Segment::Param(name.to_string()));
+            } else {
+                segments.push(Segment::Literal(segment.to_string()));
+            }
+        }
+
+        Pattern { segments, tail }
+    }
+
+    fn matches(&self, path: &str) -> Option<Params> {
+        if !path.starts_with('/') {
+            return None;
+        }
+
+        let mut rest = path[1..].split('/');
+        let mut params = Params::default();
+
+        for segment in &self.segments {
+            let value = rest.next()?;
+            match segment {
+                Segment::Literal(literal) if literal == value => {}
+                Segment::Param(name) if !value.is_empty() => {
+                    params.values.push((name.clone(), value.to_string()))
+                }
+                _ => return None,
+            }
+        }
+
+        match &self.tail {
+            Some(name) => {
+                let value = rest.collect::<Vec<_>>().join("/");
+                params.values.push((name.clone(), value));
+            }
+            None if rest.next().is_some() => return None,
+            None => {}
+        }
+
+        Some(params)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use std::sync::{Arc, Mutex};
+
+    use super::*;
+    use crate::http::{Headers, Version};
+
+    fn request(method: &str, target: &str) -> Request {
+        Request {
+            method: method.to_string(),
+            target: target.to_string(),
+            version: Version::Http11,
+            headers: Headers::new(),
+            body: Vec::new(),
+        }
+    }
+
+    // Answers with the captured parameters, as `name=value` lines.
+    fn echo(_: &Request, params: &Params) -> Response {
+        let body: String = params
+            .values
+            .iter()
+            .map(|(name, value)| format!("{}={}\n", name, value))
+            .collect();
+        Response::new(Status::OK).with_body("text/plain", body)
+    }
+
+    fn body(response: Response) -> String {
+        let mut bytes = Vec::new();
+        response.write_to(&mut bytes).unwrap();
+        let bytes = String::from_utf8(bytes).unwrap();
+        bytes[bytes.find("\r\n\r\n").unwrap() + 4..].to_string()
+    }
+
+    #[test]
+    fn matches_patterns_and_captures_params() {
+        let router = Router::new()
+            .get("/", echo)
+            .get("/users/me", |_, _| Response::plain(Status::OK))
+            .get("/users/:id", echo)
+            .get("/users/:id/posts/:post", echo)
+            .get("/static/*path", echo);
+
+        assert_eq!(body(router.handle(&request("GET", "/"))), "");
+        assert_eq!(
+            body(router.handle(&request("GET", "/users/me"))),
+            "200 OK\n"
+        );
+        assert_eq!(
+            body(router.handle(&request("GET", "/users/42?full=1"))),
+            "id=42\n"
+        );
+        assert_eq!(
+            body(router.handle(&request("GET", "/users/42/posts/a%20b%2Fc"))),
+            "id=42\npost=a b/c\n"
+        );
+        assert_eq!(
+            body(router.handle(&request("GET", "/static/css/site.css"))),
+            "path=css/site.css\n"
+        );
+        assert_eq!(body(router.handle(&request("GET", "/static/"))), "path=\n");
+
+        let response = router.handle(&request("GET", "/users/%FF"));
+        assert_eq!(response.status, Status::BAD_REQUEST);
+
+        for target in &[
+            "/users",
+            "/users/",
+            "/users/42/",
+            "/users/42/posts",
+            "/nope",
+            "*",
+        ] {
+            let response = router.handle(&request("GET", target));
+            assert_eq!(response.status, Status::NOT_FOUND, "{}", target);
+        }
+    }
+
+    #[test]
+    fn answers_other_methods_with_not_allowed() {
+        let router = Router::new()
+            .get("/users/:id", echo)
+            .route("DELETE", "/users/:id", echo)
+            .route("POST", "/users", echo)
+            .not_found(|_| Response::new(Status::NOT_FOUND).with_body("text/plain", "custom"));
+
+        let response = router.handle(&request("PUT", "/users/42"));
+        assert_eq!(response.status, Status::METHOD_NOT_ALLOWED);
+        assert_eq!(response.headers.get("Allow"), Some("DELETE, GET, HEAD"));
+
+        let response = router.handle(&request("GET", "/users"));
+        assert_eq!(response.status, Status::METHOD_NOT_ALLOWED);
+        assert_eq!(response.headers.get("Allow"), Some("POST"));
+
+        // HEAD is routed to the GET handler.
+        let response = router.handle(&request("HEAD", "/users/42"));
+        assert_eq!(response.status, Status::OK);
+
+        let response = router.handle(&request("PUT", "/posts/42"));
+        assert_eq!(response.status, Status::NOT_FOUND);
+        assert_eq!(body(response), "custom");
+    }
+
+    #[test]
+    fn runs_middleware_around_every_request() {
+        let calls = Arc::new(Mutex::new(Vec::new()));
+
+        let outer = Arc::clone(&calls);
+        let inner = Arc::clone(&calls);
+        let router = Router::new()
+            .wrap(move |request, next| {
+                outer
+                    .lock()
+                    .unwrap()
+                    .push(format!("outer {}", request.target));
+                let response = next.run(request);
+                outer
+                    .lock()
+                    .unwrap()
+                    .push(format!("outer {}", response.status));
+                response.with_header("X-Outer", "1")
+            })
+            .wrap(move |request, next| {
+                inner
+                    .lock()
+                    .unwrap()
+                    .push(format!("inner {}", request.target));
+                if request.target == "/blocked" {
+                    return Response::plain(Status::FORBIDDEN);
+                }
+                next.run(request)
+            })
+            .get("/*path", |_, _| Response::plain(Status::OK));
+
+        let response = router.handle(&request("GET", "/"));
+        assert_eq!(response.headers.get("X-Outer"), Some("1"));
+
+        let response = router.handle(&request("GET", "/blocked"));
+        assert_eq!(response.status, Status::FORBIDDEN);
+
+        let response = router.handle(&request("POST", "/"));
+        assert_eq!(response.status, Status::METHOD_NOT_ALLOWED);
+
+        assert_eq!(
+            *calls.lock().unwrap(),
+            [
+                "outer /",
+                "inner /",
+                "outer 200 OK",
+                "outer /blocked",
+                "inner /blocked",
+                "outer 403 Forbidden",
+                "outer /",
+                "inner /",
+                "outer 405 Method Not Allowed",
+            ]
+        );
+    }
+
+    #[test]
+    #[should_panic(expected = "wildcard must be at the end")]
+    fn rejects_wildcards_before_the_end() {
+        Router::new().get("/*path/more", echo);
+    }
+}
@@ -0,0 +1,232 @@
+//! Smallest prime factor sieve, for factoring many integers.
+//!
+//! A linear (Euler) sieve finds the smallest prime factor of every number less than
+//! `upper_limit`, visiting each composite exactly once.  Then any of those numbers can be
+//! factored in `O(log n)`, by repeatedly dividing it by its smallest prime factor.
+
+use crate::miller_rabin::is_prime_u64;
+
+pub struct SpfSieve {
+    upper_limit: usize,
+
+    // Smallest prime factor of each number, or zero for 0 and 1; u32 keeps the table at half the
+    // size, which limits upper_limit to 2^32.
+    spf: Vec<u32>,
+}
+
+impl SpfSieve {
+    #[cfg_attr(feature = "disass", inline(never))]
+    pub fn build(upper_limit: usize) -> Self {
+        assert!(
+            upper_limit <= u32::MAX as usize + 1,
+            "upper_limit must not exceed 2^32"
+        );
+
+        let mut spf = vec![0u32; upper_limit];
+        let mut primes: Vec<u32> = Vec::new();
+
+        for i in 2..upper_limit {
+            if spf[i] == 0 {
+                spf[i] = i as u32;
+                primes.push(i as u32);
+            }
+
+            // Every composite i * p is only reached from its largest proper divisor i, with p
+            // being its smallest prime factor, which is why p must not exceed spf[i].
+            for &p in &primes {
+                let mult = i * p as usize;
+                if p > spf[i] || mult >= upper_limit {
+                    break;
+                }
+                spf[mult] = p;
+            }
+        }
+
+        SpfSieve { upper_limit, spf }
+    }
+
+    /// Returns whether `number` is prime, falling back to [`is_prime_u64`] when it is not less
+    /// than `upper_limit`.
+    pub fn is_prime(&self, number: usize) -> bool {
+        if number < self.upper_limit {
+            number >= 2 && self.spf[number] as usize == number
+        } else {
+            is_prime_u64(number as u64)
+        }
+    }
+
+    /// Returns the smallest prime factor of `number`, or `None` for 0 and 1.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `number` is not less than `upper_limit`.
+    pub fn smallest_prime_factor(&self, number: usize) -> Option<usize> {
+        self.check(number);
+
+        match self.spf[number] {
+            0 => None,
+            p => Some(p as usize),
+        }
+    }
+
+    /// Returns the prime factorization of `number`, as `(prime, exponent)` pairs in increasing
+    /// order of the primes; the factorization of 1 is empty.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `number` is zero or not less than `upper_limit`.
+    #[cfg_attr(feature = "disass", inline(never))]
+    pub fn factorize(&self, number: usize) -> Vec<(usize, u32)> {
+        self.check(number);
+        assert!(number != 0, "zero cannot be factored");
+
+        let mut factors: Vec<(usize, u32)> = Vec::new();
+        let mut rest = number;
+
+        while rest > 1 {
+            let p = self.spf[rest] as usize;
+            rest /= p;
+
+            match factors.last_mut() {
+                Some((last, exp)) if *last == p => *exp += 1,
+                _ => factors.push((p, 1)),
+            }
+        }
+
+        factors
+    }
+
+    /// Returns all divisors of `number`, in increasing order.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `number` is zero or not less than `upper_limit`.
+    pub fn divisors(&self, number: usize) -> Vec<usize> {
+        let mut divisors = vec![1];
+
+        for (p, exp) in self.factorize(number) {
+            let len = divisors.len();
+            let mut power = 1;
+            for _ in 0..exp {
+                power *= p;
+                for i in 0..len {
+                    divisors.push(divisors[i] * power);
+                }
+            }
+        }
+
+        divisors.sort_unstable();
+        divisors
+    }
+
+    /// Returns Euler's totient of `number`, the count of integers in `1..=number` coprime to it.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `number` is zero or not less than `upper_limit`.
+    pub fn euler_phi(&self, number: usize) -> usize {
+        self.factorize(number)
+            .into_iter()
+            .fold(number, |phi, (p, _)| phi / p * (p - 1))
+    }
+
+    /// Returns the Möbius function of `number`: zero if it has a squared prime factor, and
+    /// otherwise 1 or -1 depending on whether it has an even or odd number of prime factors.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `number` is zero or not less than `upper_limit`.
+    pub fn mobius(&self, number: usize) -> i8 {
+        let factors = self.factorize(number);
+
+        if factors.iter().any(|&(_, exp)| exp > 1) {
+            0
+        } else if factors.len().is_multiple_of(2) {
+            1
+        } else {
+            -1
+        }
+    }
+
+    fn check(&self, number: usize) {
+        assert!(number < self.upper_limit, "number is beyond the sieve");
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn gcd(a: usize, b: usize) -> usize {
+        if b == 0 {
+            a
+        } else {
+            gcd(b, a % b)
+        }
+    }
+
+    #[test]
+    fn finds_smallest_prime_factors() {
+        let sieve = SpfSieve::build(10_000);
+
+        assert_eq!(sieve.smallest_prime_factor(0), None);
+        assert_eq!(sieve.smallest_prime_factor(1), None);
+
+        for n in 2..10_000 {
+            let expected = (2..=n).find(|d| n % d == 0);
+            assert_eq!(sieve.smallest_prime_factor(n), expected, "{}", n);
+        }
+    }
+
+    #[test]
+    fn agrees_with_the_sieve_and_falls_back_to_miller_rabin() {
+        let upper_limit = 100_000;
+        let spf = SpfSieve::build(upper_limit);
+        let sieve = crate::Sieve::build(2 * upper_limit);
+
+        for n in 0..2 * upper_limit {
+            assert_eq!(spf.is_prime(n), sieve.is_prime(n), "{}", n);
+        }
+    }
+
+    #[test]
+    fn factors_and_derived_functions() {
+        let sieve = SpfSieve::build(2_000);
+
+        assert!(sieve.factorize(1).is_empty());
+        assert_eq!(sieve.factorize(360), [(2, 3), (3, 2), (5, 1)]);
+        assert_eq!(sieve.factorize(1_999), [(1_999, 1)]);
+        assert_eq!(sieve.divisors(12), [1, 2, 3, 4, 6, 12]);
+
+        for n in 1..2_000 {
+            let product: usize = sieve
+                .factorize(n)
+                .iter()
+                .map(|&(p, exp)| p.pow(exp))
+                .product();
+            assert_eq!(product, n);
+
+            let divisors: Vec<_> = (1..=n).filter(|d| n % d == 0).collect();
+            assert_eq!(sieve.divisors(n), divisors, "{}", n);
+
+            let phi = (1..=n).filter(|&k| gcd(n, k) == 1).count();
+            assert_eq!(sieve.euler_phi(n), phi, "{}", n);
+
+            // By trial division, independently of the smallest prime factors in the sieve.
+            let (mut m, mut mobius) = (n, 1);
+            for d in 2..=n {
+                if m % d == 0 {
+                    m /= d;
+                    mobius = if m % d == 0 { 0 } else { -mobius };
+                }
+            }
+            assert_eq!(sieve.mobius(n), mobius, "{}", n);
+        }
+    }
+
+    #[test]
+    #[should_panic]
+    fn factorize_panics_beyond_the_sieve() {
+        SpfSieve::build(100).factorize(100);
+    }
+}
@@ -1,30 +1,75 @@
+mod factor;
+mod loops;
+mod miller_rabin;
+mod parallel;
+mod persist;
+mod presieve;
+mod primes;
+pub mod segmented;
+mod simd;
+mod storage;
+mod wheel;
+
+pub use factor::SpfSieve;
+pub use loops::{ForEach, ForLoops, LoopStrategy, WhileLoops};
+pub use miller_rabin::is_prime_u64;
+pub use primes::Primes;
+pub use segmented::SegmentedSieve;
+pub use simd::Backend;
+pub use storage::SieveStorage;
+pub use wheel::WheelSieve;
+
+use persist::Map;
+
+// The storage and loop features only select the defaults used by Sieve::build(); all strategies
+// are always available through Sieve::build_with().  When features are unified, the non-default
+// choices win.
+
 #[cfg(feature = "bool-based")]
-type C = bool;
+pub type DefaultStorage = bool;
 
 // Best chunk sizes appear to u8 and, in a close second place, usize.  But only tested on
 // Intel/x86-64 (Skylake).
-#[cfg(feature = "bit-based")]
-type C = u8;
+#[cfg(not(feature = "bool-based"))]
+pub type DefaultStorage = u8;
+
+#[cfg(feature = "for-loops")]
+pub type DefaultLoops = ForLoops;
+
+#[cfg(all(feature = "for_each", not(feature = "for-loops")))]
+pub type DefaultLoops = ForEach;
 
-#[cfg(feature = "bit-based")]
-const CBITS: usize = C::count_ones(C::MAX) as usize;
+#[cfg(not(any(feature = "for-loops", feature = "for_each")))]
+pub type DefaultLoops = WhileLoops;
 
-pub struct Sieve {
+pub struct Sieve<C: SieveStorage = DefaultStorage> {
     upper_limit: usize,
-    map: Vec<C>,
+    map: Map<C>,
 }
 
 impl Sieve {
     #[cfg_attr(feature = "disass", inline(never))]
     pub fn build(upper_limit: usize) -> Self {
+        Sieve::build_with::<DefaultLoops>(upper_limit)
+    }
+}
+
+impl<C: SieveStorage> Sieve<C> {
+    /// Builds a sieve with storage `C`, clearing the non primes with loop strategy `L`.
+    #[cfg_attr(feature = "disass", inline(never))]
+    pub fn build_with<L: LoopStrategy>(upper_limit: usize) -> Self {
         let mut sieve = Sieve::new(upper_limit);
-        sieve.clear_non_primes();
+        L::clear_non_primes(&mut sieve);
         sieve
     }
 
+    /// Returns whether `number` is prime, falling back to [`is_prime_u64`] when it is not less
+    /// than `upper_limit`.
     #[cfg_attr(feature = "disass", inline(never))]
     pub fn is_prime(&self, number: usize) -> bool {
-        if number < self.upper_limit && number % 2 == 1 {
+        if number >= self.upper_limit {
+            is_prime_u64(number as u64)
+        } else if number % 2 == 1 {
             // SAFETY: just checked that number is odd and less than upper_limit
             unsafe { self.is_prime_unchecked(number) }
         } else {
@@ -37,179 +82,41 @@ impl Sieve {
         !self.is_prime(number)
     }
 
-    #[cfg(feature = "for-loops")]
-    fn clear_non_primes(&mut self) {
-        // SAFETY: 1 is explicitly allowed
-        unsafe { self.clear_prime_unchecked(1) };
-
-        let enough = (self.upper_limit as f64).sqrt() as usize;
-
-        for factor in (3..=enough).step_by(2) {
-            // SAFETY: factor is odd (3, 5, 7, ...) and less than upper_limit
-            // (enough < upper_limit)
-            if unsafe { !self.is_prime_unchecked(factor) } {
-                continue;
-            }
-
-            for mult in ((factor * factor)..self.upper_limit).step_by(factor * 2) {
-                // SAFETY: mult is odd (prime * 3, 5, 7, ...) and less than upper_limit
-                unsafe { self.clear_prime_unchecked(mult) };
-            }
-        }
-    }
-
-    #[cfg(feature = "for_each")]
-    fn clear_non_primes(&mut self) {
-        // SAFETY: 1 is explicitly allowed
-        unsafe { self.clear_prime_unchecked(1) };
-
-        let enough = (self.upper_limit as f64).sqrt() as usize;
-
-        (3..=enough).step_by(2).for_each(|factor| {
-            // SAFETY: factor is odd (3, 5, 7, ...) and less than upper_limit
-            // (enough < upper_limit)
-            if unsafe { !self.is_prime_unchecked(factor) } {
-                return;
-            }
-
-            ((factor * factor)..self.upper_limit)
-                .step_by(factor * 2)
-                .for_each(|mult| {
-                    // SAFETY: mult is odd (prime * 3, 5, 7, ...) and less than upper_limit
-                    unsafe { self.clear_prime_unchecked(mult) };
-                });
-        });
-    }
-
-    // As of Rust 1.51, while loops are significantly faster than using a for statement or even
-    // for_each().
-    #[cfg(feature = "while-loops")]
-    fn clear_non_primes(&mut self) {
-        // SAFETY: 1 is explicitly allowed
-        unsafe { self.clear_prime_unchecked(1) };
-
-        let enough = (self.upper_limit as f64).sqrt() as usize;
-
-        let mut factor = 3;
-        while factor <= enough {
-            // SAFETY: factor is odd (3, 5, 7, ...) and less than upper_limit
-            // (enough < upper_limit)
-            if unsafe { self.is_prime_unchecked(factor) } {
-                let mut mult = factor * factor;
-
-                while mult < self.upper_limit {
-                    // SAFETY: mult is odd (prime * 3, 5, 7, ...) and less than upper_limit
-                    unsafe { self.clear_prime_unchecked(mult) };
-                    mult += factor * 2;
-                }
-            }
-            factor += 2;
-        }
-    }
-}
-
-#[cfg(feature = "bool-based")]
-impl Sieve {
     #[cfg_attr(feature = "disass", inline(never))]
     pub fn count_primes(&self) -> usize {
-        // Assumes that extra bits at the end are zeroed/cleared.
-        self.map.iter().filter(|&x| *x).count() as usize + 1
+        // Assumes that extra entries at the end are zeroed/cleared; this works for every storage,
+        // since a bool is stored as either 0 or 1.
+        Backend::detect().count_ones(storage::bytes_of(&self.map)) + 1
     }
 
-    #[cfg(feature = "bool-based")]
     #[cfg_attr(feature = "disass", inline(never))]
     fn new(upper_limit: usize) -> Self {
         Sieve {
             upper_limit,
-            map: vec![true; upper_limit / 2],
+            map: Map::Owned(C::new_map(upper_limit / 2)),
         }
     }
 
     /// # Safety
     ///
     /// The `number` argument must be one or odd and less than `self.upper_limit`.
-    #[cfg(feature = "bool-based")]
     #[cfg_attr(feature = "disass", inline(never))]
     unsafe fn clear_prime_unchecked(&mut self, number: usize) {
-        debug_assert!(number % 2 != 0);
+        debug_assert!(number % 2 == 1);
         debug_assert!(number < self.upper_limit || number == 1);
-        debug_assert!(number / 2 < self.map.len());
 
-        *self.map.get_unchecked_mut(number / 2) = false;
+        C::clear_unchecked(&mut self.map, number / 2)
     }
 
     /// # Safety
     ///
     /// The `number` argument must be odd and less `self.upper_limit`.
-    #[cfg(feature = "bool-based")]
     #[cfg_attr(feature = "disass", inline(never))]
     unsafe fn is_prime_unchecked(&self, number: usize) -> bool {
-        debug_assert!(number % 2 != 0);
+        debug_assert!(number % 2 == 1);
         debug_assert!(number < self.upper_limit);
-        debug_assert!(number / 2 < self.map.len());
 
-        *self.map.get_unchecked(number / 2)
-    }
-}
-
-#[cfg(feature = "bit-based")]
-impl Sieve {
-    #[cfg_attr(feature = "disass", inline(never))]
-    pub fn count_primes(&self) -> usize {
-        // Assumes that extra bits at the end are zeroed/cleared.
-        self.map.iter().map(|x| x.count_ones()).sum::<u32>() as usize + 1
-    }
-
-    #[cfg(feature = "bit-based")]
-    #[cfg_attr(feature = "disass", inline(never))]
-    fn new(upper_limit: usize) -> Self {
-        let mut map = vec![C::MAX as C; upper_limit / 2 / CBITS + 1];
-
-        // Zero extra bits at the end, so count_primes can use count_ones.
-        let keep = upper_limit / 2 % CBITS;
-        let keep_mask: C = (1 << keep) - 1;
-
-        let last = map.len() - 1;
-
-        // SAFETY: just computed last from map.len()
-        unsafe { *map.get_unchecked_mut(last) &= keep_mask };
-
-        Sieve { upper_limit, map }
-    }
-
-    /// # Safety
-    ///
-    /// The `number` argument must be one or odd and less than `self.upper_limit`.
-    #[cfg(feature = "bit-based")]
-    #[cfg_attr(feature = "disass", inline(never))]
-    unsafe fn clear_prime_unchecked(&mut self, number: usize) {
-        debug_assert!(number % 2 != 0);
-        debug_assert!(number < self.upper_limit || number == 1);
-
-        let number = number / 2;
-        let word = number / CBITS;
-        let bit = number % CBITS;
-
-        debug_assert!(word < self.map.len());
-        *self.map.get_unchecked_mut(word) &= !(1 << bit)
-    }
-
-    ///
-    /// # Safety
-    ///
-    /// The `number` argument must be odd and less `self.upper_limit`.
-    #[cfg(feature = "bit-based")]
-    #[cfg_attr(feature = "disass", inline(never))]
-    unsafe fn is_prime_unchecked(&self, number: usize) -> bool {
-        debug_assert!(number % 2 != 0);
-        debug_assert!(number < self.upper_limit);
-
-        let number = number / 2;
-        let word = number / CBITS;
-        let bit = number % CBITS;
-
-        debug_assert!(word < self.map.len());
-        self.map.get_unchecked(word) & (1 << bit) != 0
+        C::is_set_unchecked(&self.map, number / 2)
     }
 }
 
@@ -250,4 +157,33 @@ mod test {
 
         assert_eq!(sieve.count_primes(), 9);
     }
+
+    #[test]
+    fn every_strategy_builds_the_same_sieve() {
+        fn check<C: SieveStorage, L: LoopStrategy>(expected: &Sieve) {
+            let sieve = Sieve::<C>::build_with::<L>(expected.upper_limit);
+            assert!(
+                sieve.primes().eq(expected.primes()),
+                "{} {}",
+                C::NAME,
+                L::NAME
+            );
+        }
+
+        fn with<C: SieveStorage>(expected: &Sieve) {
+            check::<C, ForLoops>(expected);
+            check::<C, ForEach>(expected);
+            check::<C, WhileLoops>(expected);
+        }
+
+        for &upper_limit in &[2, 3, 25, 1_000, 100_001] {
+            let expected = Sieve::build(upper_limit);
+
+            with::<bool>(&expected);
+            with::<u8>(&expected);
+            with::<u32>(&expected);
+            with::<u64>(&expected);
+            with::<usize>(&expected);
+        }
+    }
 }
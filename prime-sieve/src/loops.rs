@@ -0,0 +1,116 @@
+//! Loop strategies for clearing the non primes of a [`Sieve`].
+//!
+//! All strategies clear exactly the same entries; they only differ in how the loops are written,
+//! which can make a surprising difference in the generated code.
+
+use crate::{storage::SieveStorage, Sieve};
+
+pub trait LoopStrategy {
+    /// Short name of the strategy, for reporting.
+    const NAME: &'static str;
+
+    fn clear_non_primes<C: SieveStorage>(sieve: &mut Sieve<C>) {
+        Self::clear_non_primes_from(sieve, 3);
+    }
+
+    /// Clears the non primes that are multiples of odd factors starting at `first`, which must be
+    /// odd; the multiples of smaller factors must have already been cleared (e.g. by pre-sieving).
+    fn clear_non_primes_from<C: SieveStorage>(sieve: &mut Sieve<C>, first: usize);
+}
+
+/// Nested `for` loops over stepped ranges.
+pub struct ForLoops;
+
+/// Nested `for_each` calls on stepped ranges.
+pub struct ForEach;
+
+/// Nested `while` loops.
+pub struct WhileLoops;
+
+impl LoopStrategy for ForLoops {
+    const NAME: &'static str = "for-loops";
+
+    #[cfg_attr(feature = "disass", inline(never))]
+    fn clear_non_primes_from<C: SieveStorage>(sieve: &mut Sieve<C>, first: usize) {
+        assert!(first % 2 == 1, "first factor must be odd");
+
+        // SAFETY: 1 is explicitly allowed
+        unsafe { sieve.clear_prime_unchecked(1) };
+
+        let enough = (sieve.upper_limit as f64).sqrt() as usize;
+
+        for factor in (first..=enough).step_by(2) {
+            // SAFETY: factor is odd (first, first + 2, ...) and less than upper_limit
+            // (enough < upper_limit)
+            if unsafe { !sieve.is_prime_unchecked(factor) } {
+                continue;
+            }
+
+            for mult in ((factor * factor)..sieve.upper_limit).step_by(factor * 2) {
+                // SAFETY: mult is odd (prime * 3, 5, 7, ...) and less than upper_limit
+                unsafe { sieve.clear_prime_unchecked(mult) };
+            }
+        }
+    }
+}
+
+impl LoopStrategy for ForEach {
+    const NAME: &'static str = "for_each";
+
+    #[cfg_attr(feature = "disass", inline(never))]
+    fn clear_non_primes_from<C: SieveStorage>(sieve: &mut Sieve<C>, first: usize) {
+        assert!(first % 2 == 1, "first factor must be odd");
+
+        // SAFETY: 1 is explicitly allowed
+        unsafe { sieve.clear_prime_unchecked(1) };
+
+        let enough = (sieve.upper_limit as f64).sqrt() as usize;
+
+        (first..=enough).step_by(2).for_each(|factor| {
+            // SAFETY: factor is odd (first, first + 2, ...) and less than upper_limit
+            // (enough < upper_limit)
+            if unsafe { !sieve.is_prime_unchecked(factor) } {
+                return;
+            }
+
+            ((factor * factor)..sieve.upper_limit)
+                .step_by(factor * 2)
+                .for_each(|mult| {
+                    // SAFETY: mult is odd (prime * 3, 5, 7, ...) and less than upper_limit
+                    unsafe { sieve.clear_prime_unchecked(mult) };
+                });
+        });
+    }
+}
+
+// As of Rust 1.51, while loops are significantly faster than using a for statement or even
+// for_each().
+impl LoopStrategy for WhileLoops {
+    const NAME: &'static str = "while-loops";
+
+    #[cfg_attr(feature = "disass", inline(never))]
+    fn clear_non_primes_from<C: SieveStorage>(sieve: &mut Sieve<C>, first: usize) {
+        assert!(first % 2 == 1, "first factor must be odd");
+
+        // SAFETY: 1 is explicitly allowed
+        unsafe { sieve.clear_prime_unchecked(1) };
+
+        let enough = (sieve.upper_limit as f64).sqrt() as usize;
+
+        let mut factor = first;
+        while factor <= enough {
+            // SAFETY: factor is odd (first, first + 2, ...) and less than upper_limit
+            // (enough < upper_limit)
+            if unsafe { sieve.is_prime_unchecked(factor) } {
+                let mut mult = factor * factor;
+
+                while mult < sieve.upper_limit {
+                    // SAFETY: mult is odd (prime * 3, 5, 7, ...) and less than upper_limit
+                    unsafe { sieve.clear_prime_unchecked(mult) };
+                    mult += factor * 2;
+                }
+            }
+            factor += 2;
+        }
+    }
+}
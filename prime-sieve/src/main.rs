@@ -1,13 +1,34 @@
 //! Measure how many sieves can be executed in a given period.
+//!
+//! By default, the sieves are built with the default storage and loop strategy; another strategy
+//! can be selected with `--strategy NAME`, and with `all`, every strategy is measured in turn:
+//! each combination of storage and loop strategy, parallel construction with each storage (on
+//! `--threads` threads, by default as many as available CPUs), pre-sieving, and the mod 30 wheel.
+//! The `--period SECONDS` and `--limit UPPER_LIMIT` options change the defaults of 10 seconds and
+//! 1,000,000 (the upper limit must be at least 3), and `--format text|csv|json` selects between a
+//! short sentence for each strategy and the fields of the "Primes" drag race (label, passes,
+//! duration, threads, algorithm, faithful, bits), in CSV or JSON.
+//!
+//! With `parallel [THREADS]`, the sieves are built with `Sieve::build_parallel` instead; this is a
+//! shorthand for the parallel strategy with the default storage.
+//!
+//! Alternatively, with `validate [UPPER_LIMIT...]`, check the prime counts for the given upper
+//! limits (or for every entry in the table) with the segmented sieve, which can reach the larger
+//! entries in bounded memory.  And with `crosscheck [MAX_UPPER_LIMIT]`, check that the odd-only
+//! and the mod 30 wheel sieves agree with each other and with every entry in the table that fits
+//! in memory (by default, up to 10^9).
 
-use prime_sieve::Sieve;
+use prime_sieve::{
+    Backend, DefaultLoops, DefaultStorage, ForEach, ForLoops, LoopStrategy, SegmentedSieve, Sieve,
+    SieveStorage, WheelSieve, WhileLoops,
+};
 use std::collections::HashMap;
+use std::env;
+use std::mem;
+use std::thread;
 use std::time::Instant;
 
 fn main() {
-    let period = 10.;
-    let upper_limit: usize = 1_000_000;
-
     let prime_counts = [
         (10, 4),
         (100, 25),
@@ -26,21 +47,425 @@ fn main() {
     .copied()
     .collect::<HashMap<usize, usize>>();
 
+    let mut options = Options::parse(env::args().skip(1));
+    let mut args = mem::take(&mut options.args).into_iter();
+
+    let strategies = strategies();
+    let selected: Vec<&Strategy> = match args.next().as_deref() {
+        Some("validate") => {
+            return validate(
+                &prime_counts,
+                args.map(|arg| arg.parse().expect("invalid upper limit")),
+            )
+        }
+        Some("crosscheck") => {
+            let max = match args.next() {
+                Some(arg) => arg.parse().expect("invalid upper limit"),
+                None => 1_000_000_000,
+            };
+            return crosscheck(&prime_counts, max);
+        }
+        Some("all") => strategies.iter().collect(),
+        Some("parallel") => {
+            if let Some(arg) = args.next() {
+                options.threads = arg.parse().expect("invalid number of threads");
+            }
+            vec![find(
+                &strategies,
+                &format!("{}-parallel", DefaultStorage::NAME),
+            )]
+        }
+        Some(mode) => panic!("unknown mode: {}", mode),
+        None => {
+            let label = match &options.strategy {
+                Some(label) => label.clone(),
+                None => format!("{}-{}", DefaultStorage::NAME, DefaultLoops::NAME),
+            };
+            vec![find(&strategies, &label)]
+        }
+    };
+
+    // Upper limits that are not in the table are first counted with the segmented sieve.
+    let expected = match prime_counts.get(&options.upper_limit) {
+        Some(&count) => count,
+        None => SegmentedSieve::new(options.upper_limit).count_primes(),
+    };
+
+    let mut report = Report::new(options.format);
+    for strategy in selected {
+        let threads = if strategy.parallel {
+            options.threads
+        } else {
+            1
+        };
+
+        let (passes, duration) = bench(options.period, expected, || {
+            (strategy.build)(options.upper_limit, threads)
+        });
+
+        report.add(strategy, passes, duration, threads);
+    }
+    report.finish();
+}
+
+struct Options {
+    period: f64,
+    upper_limit: usize,
+    threads: usize,
+    strategy: Option<String>,
+    format: Format,
+
+    // The mode and its arguments.
+    args: Vec<String>,
+}
+
+impl Options {
+    fn parse(mut args: impl Iterator<Item = String>) -> Self {
+        let mut options = Options {
+            period: 10.,
+            upper_limit: 1_000_000,
+            threads: thread::available_parallelism().map_or(1, |n| n.get()),
+            strategy: None,
+            format: Format::Text,
+            args: Vec::new(),
+        };
+
+        while let Some(arg) = args.next() {
+            let mut value = || {
+                args.next()
+                    .unwrap_or_else(|| panic!("missing value for {}", arg))
+            };
+
+            match arg.as_str() {
+                "--period" => options.period = value().parse().expect("invalid period"),
+                "--limit" => {
+                    options.upper_limit = value().parse().expect("invalid upper limit");
+
+                    // The sieves only store odd numbers, and always count 2 as a prime.
+                    assert!(
+                        options.upper_limit >= 3,
+                        "invalid upper limit: must be at least 3"
+                    );
+                }
+                "--threads" => {
+                    options.threads = value().parse().expect("invalid number of threads")
+                }
+                "--strategy" => options.strategy = Some(value()),
+                "--format" => {
+                    options.format = match value().as_str() {
+                        "text" => Format::Text,
+                        "csv" => Format::Csv,
+                        "json" => Format::Json,
+                        format => panic!("unknown format: {}", format),
+                    }
+                }
+                option if option.starts_with("--") => panic!("unknown option: {}", option),
+                _ => options.args.push(arg),
+            }
+        }
+
+        options
+    }
+}
+
+// Builds sieves (checking how many primes they found) for the given period, and returns the number
+// of passes and the total duration.
+fn bench(period: f64, expected: usize, build: impl Fn() -> usize) -> (usize, f64) {
     let start = Instant::now();
     let mut duration = 0.;
     let mut passes = 0;
 
     while duration < period {
-        let sieve = Sieve::build(upper_limit);
-        assert_eq!(sieve.count_primes(), prime_counts[&upper_limit]);
+        assert_eq!(build(), expected);
         passes += 1;
         duration = Instant::now().duration_since(start).as_secs_f64();
     }
 
-    println!(
-        "{} passes in {:.1} seconds; on average, each pass took {:.2E} seconds",
-        passes,
-        duration,
-        duration / passes as f64
-    );
+    (passes, duration)
+}
+
+#[derive(Clone, Copy)]
+enum Format {
+    Text,
+    Csv,
+    Json,
+}
+
+struct Report {
+    format: Format,
+    entries: usize,
+}
+
+impl Report {
+    fn new(format: Format) -> Self {
+        match format {
+            Format::Text => {}
+            Format::Csv => println!("label,passes,duration,threads,algorithm,faithful,bits"),
+            Format::Json => println!("["),
+        }
+
+        Report { format, entries: 0 }
+    }
+
+    fn add(&mut self, strategy: &Strategy, passes: usize, duration: f64, threads: usize) {
+        let faithful = if strategy.faithful { "yes" } else { "no" };
+
+        match self.format {
+            Format::Text => {
+                if strategy.parallel {
+                    print!("[{}, {} threads] ", strategy.label, threads);
+                } else {
+                    print!("[{}] ", strategy.label);
+                }
+                println!(
+                    "{} passes in {:.1} seconds; on average, each pass took {:.2E} seconds",
+                    passes,
+                    duration,
+                    duration / passes as f64
+                );
+            }
+            Format::Csv => println!(
+                "{},{},{:.6},{},{},{},{}",
+                strategy.label,
+                passes,
+                duration,
+                threads,
+                strategy.algorithm,
+                faithful,
+                strategy.bits
+            ),
+            Format::Json => {
+                if self.entries > 0 {
+                    println!(",");
+                }
+                print!(
+                    "  {{\"label\": {}, \"passes\": {}, \"duration\": {:.6}, \"threads\": {}, \
+                     \"algorithm\": {}, \"faithful\": {}, \"bits\": {}}}",
+                    json_string(&strategy.label),
+                    passes,
+                    duration,
+                    threads,
+                    json_string(strategy.algorithm),
+                    json_string(faithful),
+                    strategy.bits
+                );
+            }
+        }
+
+        self.entries += 1;
+    }
+
+    fn finish(self) {
+        if let Format::Json = self.format {
+            if self.entries > 0 {
+                println!();
+            }
+            println!("]");
+        }
+    }
+}
+
+// Quotes and escapes a string for JSON, which (unlike Debug formatting) only knows the short
+// escapes below and \uXXXX escapes of UTF-16 code units; only control characters need the latter.
+fn json_string(s: &str) -> String {
+    let mut json = String::with_capacity(s.len() + 2);
+    json.push('"');
+
+    for c in s.chars() {
+        match c {
+            '"' => json.push_str("\\\""),
+            '\\' => json.push_str("\\\\"),
+            '\n' => json.push_str("\\n"),
+            '\r' => json.push_str("\\r"),
+            '\t' => json.push_str("\\t"),
+            c if c < ' ' => json.push_str(&format!("\\u{:04x}", c as u32)),
+            c => json.push(c),
+        }
+    }
+
+    json.push('"');
+    json
+}
+
+// Builds a sieve for an upper limit on a number of threads, and returns how many primes it found.
+type Build = fn(usize, usize) -> usize;
+
+#[derive(Clone)]
+struct Strategy {
+    label: String,
+    build: Build,
+    parallel: bool,
+
+    // Tags of the "Primes" drag race.
+    algorithm: &'static str,
+    faithful: bool,
+    bits: usize,
+}
+
+// Every combination of storage and loop strategy, parallel construction with each storage, as
+// well as pre-sieving and the mod 30 wheel.
+fn strategies() -> Vec<Strategy> {
+    fn with<C: SieveStorage>() -> Vec<Strategy> {
+        fn entry<C: SieveStorage, L: LoopStrategy>() -> Strategy {
+            Strategy {
+                label: format!("{}-{}", C::NAME, L::NAME),
+                build: |upper_limit, _| Sieve::<C>::build_with::<L>(upper_limit).count_primes(),
+                parallel: false,
+                algorithm: "base",
+                faithful: true,
+                bits: bits::<C>(),
+            }
+        }
+
+        vec![
+            entry::<C, ForLoops>(),
+            entry::<C, ForEach>(),
+            entry::<C, WhileLoops>(),
+            Strategy {
+                label: format!("{}-parallel", C::NAME),
+                build: |upper_limit, threads| {
+                    Sieve::<C>::build_parallel_with(upper_limit, threads).count_primes()
+                },
+                parallel: true,
+                algorithm: "base",
+                faithful: true,
+                bits: bits::<C>(),
+            },
+        ]
+    }
+
+    [
+        with::<bool>(),
+        with::<u8>(),
+        with::<u32>(),
+        with::<u64>(),
+        with::<usize>(),
+        vec![
+            Strategy {
+                label: format!("presieved-{}", Backend::detect().name()),
+                build: |upper_limit, _| Sieve::build_presieved(upper_limit).count_primes(),
+                parallel: false,
+                algorithm: "other",
+                faithful: true,
+                bits: bits::<DefaultStorage>(),
+            },
+            Strategy {
+                label: String::from("wheel-30"),
+                build: |upper_limit, _| WheelSieve::build(upper_limit).count_primes(),
+                parallel: false,
+                algorithm: "wheel",
+                faithful: true,
+                bits: 1,
+            },
+        ],
+    ]
+    .concat()
+}
+
+// Bits used by each entry of the map.
+fn bits<C: SieveStorage>() -> usize {
+    mem::size_of::<C>() * 8 / C::ENTRIES
+}
+
+fn find<'a>(strategies: &'a [Strategy], label: &str) -> &'a Strategy {
+    strategies
+        .iter()
+        .find(|strategy| strategy.label == label)
+        .unwrap_or_else(|| {
+            let labels: Vec<_> = strategies.iter().map(|s| s.label.as_str()).collect();
+            panic!(
+                "unknown strategy: {} (one of: {})",
+                label,
+                labels.join(", ")
+            )
+        })
+}
+
+fn validate(prime_counts: &HashMap<usize, usize>, upper_limits: impl Iterator<Item = usize>) {
+    let mut upper_limits: Vec<usize> = upper_limits.collect();
+    if upper_limits.is_empty() {
+        upper_limits = prime_counts.keys().copied().collect();
+    }
+    upper_limits.sort_unstable();
+
+    for upper_limit in upper_limits {
+        let expected = prime_counts
+            .get(&upper_limit)
+            .expect("upper limit not in the prime_counts table");
+
+        let start = Instant::now();
+        let count = SegmentedSieve::new(upper_limit).count_primes();
+        let duration = Instant::now().duration_since(start).as_secs_f64();
+
+        assert_eq!(count, *expected, "wrong count for {}", upper_limit);
+        println!(
+            "{} primes bellow {} (validated in {:.1} seconds)",
+            count, upper_limit, duration
+        );
+    }
+}
+
+fn crosscheck(prime_counts: &HashMap<usize, usize>, max_upper_limit: usize) {
+    let mut upper_limits: Vec<usize> = prime_counts
+        .keys()
+        .copied()
+        .filter(|&upper_limit| upper_limit <= max_upper_limit)
+        .collect();
+    upper_limits.sort_unstable();
+
+    for upper_limit in upper_limits {
+        let sieve = Sieve::build(upper_limit);
+        let wheel = WheelSieve::build(upper_limit);
+
+        assert_eq!(sieve.count_primes(), prime_counts[&upper_limit]);
+        assert_eq!(wheel.count_primes(), prime_counts[&upper_limit]);
+        assert!(
+            sieve.primes().all(|p| wheel.is_prime(p)),
+            "sieves disagree bellow {}",
+            upper_limit
+        );
+
+        println!(
+            "{} primes bellow {} (cross-checked)",
+            prime_counts[&upper_limit], upper_limit
+        );
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn parse(args: &[&str]) -> Options {
+        Options::parse(args.iter().map(|arg| arg.to_string()))
+    }
+
+    #[test]
+    #[should_panic(expected = "must be at least 3")]
+    fn rejects_limits_below_3() {
+        parse(&["--limit", "2"]);
+    }
+
+    #[test]
+    fn escapes_json_strings() {
+        assert_eq!(json_string("u8-while-loops"), "\"u8-while-loops\"");
+        assert_eq!(json_string("a\"b\\c"), "\"a\\\"b\\\\c\"");
+        assert_eq!(json_string("\n\t\u{1}\u{1f}"), "\"\\n\\t\\u0001\\u001f\"");
+
+        // Unlike Debug formatting, which would escape these as \u{...}.
+        assert_eq!(json_string("\u{200b}é"), "\"\u{200b}é\"");
+    }
+
+    #[test]
+    fn every_strategy_counts_small_limits() {
+        for upper_limit in 3..=100 {
+            let options = parse(&["--period", "0", "--limit", &upper_limit.to_string()]);
+            let expected = SegmentedSieve::new(options.upper_limit).count_primes();
+
+            for strategy in strategies() {
+                let count = (strategy.build)(options.upper_limit, 2);
+                assert_eq!(count, expected, "{} {}", strategy.label, upper_limit);
+            }
+        }
+    }
 }
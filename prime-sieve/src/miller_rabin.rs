@@ -0,0 +1,93 @@
+//! Deterministic Miller-Rabin primality test, for numbers beyond the reach of a sieve.
+
+// Testing against the first 12 primes as bases is enough for every number less than 2^64
+// (3.3 * 10^24, in fact).
+const BASES: [u64; 12] = [2, 3, 5, 7, 11, 13, 17, 19, 23, 29, 31, 37];
+
+/// Returns whether `number` is prime, for any `u64`.
+#[cfg_attr(feature = "disass", inline(never))]
+pub fn is_prime_u64(number: u64) -> bool {
+    if number < 2 {
+        return false;
+    }
+
+    // Also takes care of the bases themselves, which the loop bellow cannot handle.
+    for &p in &BASES {
+        if number.is_multiple_of(p) {
+            return number == p;
+        }
+    }
+
+    // Write number - 1 as d * 2^s, with d odd.
+    let s = (number - 1).trailing_zeros();
+    let d = (number - 1) >> s;
+
+    'bases: for &a in &BASES {
+        let mut x = pow_mod(a, d, number);
+        if x == 1 || x == number - 1 {
+            continue;
+        }
+
+        for _ in 1..s {
+            x = mul_mod(x, x, number);
+            if x == number - 1 {
+                continue 'bases;
+            }
+        }
+
+        // a is a witness that number is composite.
+        return false;
+    }
+
+    true
+}
+
+fn mul_mod(a: u64, b: u64, m: u64) -> u64 {
+    (a as u128 * b as u128 % m as u128) as u64
+}
+
+fn pow_mod(mut base: u64, mut exp: u64, m: u64) -> u64 {
+    let mut result = 1;
+    base %= m;
+
+    while exp > 0 {
+        if exp & 1 == 1 {
+            result = mul_mod(result, base, m);
+        }
+        base = mul_mod(base, base, m);
+        exp >>= 1;
+    }
+
+    result
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::Sieve;
+
+    #[test]
+    fn agrees_with_the_sieve() {
+        let sieve = Sieve::build(100_000);
+
+        for n in 0..100_000 {
+            assert_eq!(is_prime_u64(n as u64), sieve.is_prime(n), "{}", n);
+        }
+    }
+
+    #[test]
+    fn handles_large_numbers_and_pseudoprimes() {
+        // Largest primes bellow 2^32 and 2^64.
+        assert!(is_prime_u64(4_294_967_291));
+        assert!(is_prime_u64(18_446_744_073_709_551_557));
+        assert!(!is_prime_u64(u64::MAX));
+
+        // Carmichael numbers, and strong pseudoprimes to several of the first prime bases.
+        assert!(!is_prime_u64(561));
+        assert!(!is_prime_u64(3_215_031_751));
+        assert!(!is_prime_u64(3_825_123_056_546_413_051));
+
+        // Squares of large primes.
+        assert!(!is_prime_u64(4_294_967_291 * 4_294_967_291));
+    }
+}
@@ -0,0 +1,125 @@
+//! Multi-threaded construction of a [`Sieve`].
+//!
+//! After the base primes up to `sqrt(upper_limit)` are found, the map is split into disjoint
+//! blocks of whole chunks, which worker threads take turns sieving.  Since no chunk is shared
+//! between blocks, the workers never touch the same memory, and the result is exactly the map
+//! that `Sieve::build` would produce.
+
+use std::{mem, sync::Mutex, thread};
+
+use crate::{segmented::L2_SEGMENT_BYTES, storage::SieveStorage, Sieve};
+
+impl Sieve {
+    /// Builds the same sieve as [`Sieve::build`], but with the work split over `threads` threads.
+    #[cfg_attr(feature = "disass", inline(never))]
+    pub fn build_parallel(upper_limit: usize, threads: usize) -> Self {
+        Sieve::build_parallel_with(upper_limit, threads)
+    }
+}
+
+impl<C: SieveStorage> Sieve<C> {
+    /// Like [`Sieve::build_parallel`], but with storage `C`.
+    #[cfg_attr(feature = "disass", inline(never))]
+    pub fn build_parallel_with(upper_limit: usize, threads: usize) -> Self {
+        // Each block should fit in L2, like the segments of a SegmentedSieve.
+        let block_chunks = L2_SEGMENT_BYTES / mem::size_of::<C>();
+
+        assert!(threads > 0, "at least one thread is required");
+
+        let enough = (upper_limit as f64).sqrt() as usize;
+        let base = Sieve::build(enough.max(2) + 1);
+        let primes: Vec<usize> = (3..=enough)
+            .step_by(2)
+            .filter(|&n| base.is_prime(n))
+            .collect();
+
+        let mut sieve = Sieve::new(upper_limit);
+        let blocks = Mutex::new(sieve.map.chunks_mut(block_chunks).enumerate());
+
+        thread::scope(|s| {
+            for _ in 0..threads {
+                s.spawn(|| loop {
+                    // Only hold the lock while taking the next block.
+                    let next = blocks.lock().unwrap().next();
+                    match next {
+                        Some((i, block)) => {
+                            let first = i * block_chunks * C::ENTRIES;
+                            clear_block(block, first, upper_limit, &primes);
+                        }
+                        None => break,
+                    }
+                });
+            }
+        });
+
+        sieve
+    }
+}
+
+// Clears the non primes in a block of the map, whose first entry is the one at index `first`
+// (and stands for the number 2 * first + 1).
+#[cfg_attr(feature = "disass", inline(never))]
+fn clear_block<C: SieveStorage>(
+    block: &mut [C],
+    first: usize,
+    upper_limit: usize,
+    primes: &[usize],
+) {
+    let low = 2 * first + 1;
+    let high = upper_limit.min(2 * (first + block.len() * C::ENTRIES) + 1);
+
+    if first == 0 && !block.is_empty() {
+        // SAFETY: the block is not empty, and 1 is at index 0
+        unsafe { C::clear_unchecked(block, 0) };
+    }
+
+    for &prime in primes {
+        // Base primes are sorted, so no later prime has multiples to clear either.
+        if prime * prime >= high {
+            break;
+        }
+
+        // Start at the first odd multiple in low..high, but never bellow the square of the prime
+        // (which also keeps the prime itself set).
+        let mut mult = (prime * prime).max(low.div_ceil(prime) * prime);
+        if mult % 2 == 0 {
+            mult += prime;
+        }
+
+        while mult < high {
+            // SAFETY: mult is odd and in low..high, so its index relative to the block is less
+            // than block.len() * C::ENTRIES
+            unsafe { C::clear_unchecked(block, mult / 2 - first) };
+            mult += prime * 2;
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::WhileLoops;
+
+    fn check<C: SieveStorage>() {
+        // Numbers covered by a block.
+        let block = 2 * L2_SEGMENT_BYTES / mem::size_of::<C>() * C::ENTRIES;
+        let limits = [2, 3, 10, 25, 1_000, block, block + 1, block + 2, 5_000_001];
+
+        for &upper_limit in &limits {
+            let expected = Sieve::<C>::build_with::<WhileLoops>(upper_limit);
+
+            for &threads in &[1, 2, 3, 8] {
+                let sieve = Sieve::<C>::build_parallel_with(upper_limit, threads);
+                assert!(*sieve.map == *expected.map, "{} {}", upper_limit, threads);
+                assert_eq!(sieve.count_primes(), expected.count_primes());
+            }
+        }
+    }
+
+    #[test]
+    fn builds_the_same_map_as_the_regular_sieve() {
+        check::<bool>();
+        check::<u8>();
+        check::<u64>();
+    }
+}
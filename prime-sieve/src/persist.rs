@@ -0,0 +1,396 @@
+//! Saving a built [`Sieve`] to a file, and loading it back or memory-mapping it.
+//!
+//! The file is a fixed size header followed by the raw bytes of the map, both in native byte
+//! order.  The header records the format version, the storage kind and chunk type, the upper
+//! limit and a checksum of all of these and the map, and since its size is a multiple of the
+//! alignment of every chunk type, a memory-mapped file can be used in place.
+
+use std::{
+    convert::TryInto,
+    fs::File,
+    io::{self, Read, Write},
+    mem,
+    ops::{Deref, DerefMut},
+    path::Path,
+    slice,
+};
+
+use memmap2::Mmap;
+
+use crate::{
+    storage::{bytes_of, bytes_of_mut, SieveStorage},
+    Sieve,
+};
+
+const MAGIC: [u8; 8] = *b"PRIMESV\0";
+
+// A file from a machine with a different byte order also fails the version check.
+const VERSION: u32 = 2;
+
+const HEADER_LEN: usize = 40;
+
+// The checksum is the last field of the header, and covers all others.
+const CHECKSUM_AT: usize = 32;
+
+// How many bytes of the map read_from reads (and allocates) at a time.
+const READ_STEP: usize = 1 << 20;
+
+// Storage kinds: one entry per chunk, or one entry per bit.
+const KIND_ENTRIES: u32 = 0;
+const KIND_BITS: u32 = 1;
+
+// FNV-1a parameters.
+const FNV_OFFSET: u64 = 0xcbf2_9ce4_8422_2325;
+const FNV_PRIME: u64 = 0x0000_0100_0000_01b3;
+
+/// The map of a [`Sieve`], either in memory or in a memory-mapped file.
+pub(crate) enum Map<C> {
+    Owned(Vec<C>),
+    Mapped(Mmap),
+}
+
+impl<C: SieveStorage> Deref for Map<C> {
+    type Target = [C];
+
+    fn deref(&self) -> &[C] {
+        match self {
+            Map::Owned(map) => map,
+            Map::Mapped(mmap) => {
+                let bytes = &mmap[HEADER_LEN..];
+
+                // SAFETY: mmap is page aligned and HEADER_LEN is a multiple of the alignment of
+                // C, and map_file already checked that the bytes hold valid chunks
+                unsafe {
+                    slice::from_raw_parts(
+                        bytes.as_ptr() as *const C,
+                        bytes.len() / mem::size_of::<C>(),
+                    )
+                }
+            }
+        }
+    }
+}
+
+impl<C: SieveStorage> DerefMut for Map<C> {
+    fn deref_mut(&mut self) -> &mut [C] {
+        match self {
+            Map::Owned(map) => map,
+            Map::Mapped(_) => unreachable!("memory-mapped sieves are never cleared"),
+        }
+    }
+}
+
+impl<C: SieveStorage> Sieve<C> {
+    /// Writes the sieve to `writer`, in the format read by [`Sieve::read_from`] and
+    /// [`Sieve::map_file`].
+    pub fn write_to<W: Write>(&self, mut writer: W) -> io::Result<()> {
+        let map = bytes_of(&self.map);
+
+        writer.write_all(&header::<C>(self.upper_limit, map))?;
+        writer.write_all(map)
+    }
+
+    /// Reads a sieve written by [`Sieve::write_to`].
+    ///
+    /// Fails with [`io::ErrorKind::InvalidData`] if the header does not match storage `C`, if
+    /// the header or the map is corrupted or truncated, or if the map would not fit in memory.
+    pub fn read_from<R: Read>(mut reader: R) -> io::Result<Self> {
+        let mut header = [0; HEADER_LEN];
+        reader.read_exact(&mut header)?;
+        let (upper_limit, expected) = parse_header::<C>(&header)?;
+
+        // The header is only checked together with the map, so a corrupted upper_limit must not
+        // be trusted to allocate the map up front: even if the allocation succeeds (which it does
+        // on systems that overcommit), filling it touches every page.  Instead, the map grows in
+        // bounded steps, as the data actually arrives.
+        let len = map_len::<C>(upper_limit);
+        let step = (READ_STEP / mem::size_of::<C>()).max(1);
+        let mut map = Vec::new();
+        while map.len() < len {
+            let filled = map.len();
+            let end = filled + step.min(len - filled);
+            map.try_reserve(end - filled)
+                .map_err(|_| invalid("upper_limit too large to load"))?;
+
+            // Any valid chunk will do, since all of them are overwritten.
+            map.resize(end, C::new_map(1)[0]);
+
+            // SAFETY: the chunks are only used after check_map has validated them
+            reader
+                .read_exact(unsafe { bytes_of_mut(&mut map[filled..]) })
+                .map_err(|err| match err.kind() {
+                    // Like in map_file, which sees the whole size up front.
+                    io::ErrorKind::UnexpectedEof => invalid("map size does not match upper_limit"),
+                    _ => err,
+                })?;
+        }
+
+        check_map::<C>(&header, bytes_of(&map), expected)?;
+
+        Ok(Sieve {
+            upper_limit,
+            map: Map::Owned(map),
+        })
+    }
+
+    /// Memory-maps a file written by [`Sieve::write_to`], without copying the map.
+    ///
+    /// The header and the map are validated like in [`Sieve::read_from`], which reads the whole
+    /// file once.
+    ///
+    /// # Safety
+    ///
+    /// The file must not be modified (by this or any other process) while the sieve is alive.
+    pub unsafe fn map_file<P: AsRef<Path>>(path: P) -> io::Result<Self> {
+        let file = File::open(path)?;
+        let mmap = Mmap::map(&file)?;
+
+        if mmap.len() < HEADER_LEN {
+            return Err(invalid("truncated header"));
+        }
+
+        let (upper_limit, expected) = parse_header::<C>(&mmap[..HEADER_LEN])?;
+
+        let bytes = &mmap[HEADER_LEN..];
+        if bytes.len() != map_len::<C>(upper_limit) * mem::size_of::<C>() {
+            return Err(invalid("map size does not match upper_limit"));
+        }
+        check_map::<C>(&mmap[..HEADER_LEN], bytes, expected)?;
+
+        Ok(Sieve {
+            upper_limit,
+            map: Map::Mapped(mmap),
+        })
+    }
+}
+
+fn header<C: SieveStorage>(upper_limit: usize, map: &[u8]) -> [u8; HEADER_LEN] {
+    let kind = if C::ENTRIES == 1 {
+        KIND_ENTRIES
+    } else {
+        KIND_BITS
+    };
+
+    let mut header = [0; HEADER_LEN];
+    header[0..8].copy_from_slice(&MAGIC);
+    header[8..12].copy_from_slice(&VERSION.to_ne_bytes());
+    header[12..16].copy_from_slice(&kind.to_ne_bytes());
+    header[16..24].copy_from_slice(&chunk_name::<C>());
+    header[24..32].copy_from_slice(&(upper_limit as u64).to_ne_bytes());
+
+    let checksum = checksum(&header[..CHECKSUM_AT], map);
+    header[CHECKSUM_AT..].copy_from_slice(&checksum.to_ne_bytes());
+    header
+}
+
+// Returns the upper limit and the checksum of the map.
+fn parse_header<C: SieveStorage>(bytes: &[u8]) -> io::Result<(usize, u64)> {
+    let field = |range: std::ops::Range<usize>| &bytes[range];
+
+    if field(0..8) != MAGIC {
+        return Err(invalid("not a sieve file"));
+    }
+
+    if field(8..12) != VERSION.to_ne_bytes() {
+        return Err(invalid("unsupported sieve file version or byte order"));
+    }
+
+    let expected = header::<C>(0, &[]);
+    if field(12..24) != &expected[12..24] {
+        let name = field(16..24).split(|&b| b == 0).next().unwrap();
+        return Err(invalid(&format!(
+            "sieve was built with {} chunks, not {}",
+            String::from_utf8_lossy(name),
+            C::NAME
+        )));
+    }
+
+    let upper_limit = u64::from_ne_bytes(field(24..32).try_into().unwrap())
+        .try_into()
+        .map_err(|_| invalid("upper_limit too large for this platform"))?;
+    let checksum = u64::from_ne_bytes(field(CHECKSUM_AT..HEADER_LEN).try_into().unwrap());
+
+    Ok((upper_limit, checksum))
+}
+
+fn check_map<C: SieveStorage>(header: &[u8], bytes: &[u8], expected: u64) -> io::Result<()> {
+    if checksum(&header[..CHECKSUM_AT], bytes) != expected {
+        return Err(invalid("checksum mismatch"));
+    }
+
+    if !C::check_bytes(bytes) {
+        return Err(invalid("invalid chunks in map"));
+    }
+
+    Ok(())
+}
+
+fn chunk_name<C: SieveStorage>() -> [u8; 8] {
+    let mut name = [0; 8];
+    name[..C::NAME.len()].copy_from_slice(C::NAME.as_bytes());
+    name
+}
+
+// Number of chunks in the map for upper_limit, as allocated by SieveStorage::new_map.
+fn map_len<C: SieveStorage>(upper_limit: usize) -> usize {
+    let entries = upper_limit / 2;
+
+    if C::ENTRIES == 1 {
+        entries
+    } else {
+        entries / C::ENTRIES + 1
+    }
+}
+
+// FNV-1a of the header fields followed by the map, but taking 8 bytes at a time, which is fast
+// enough to check a large map on every load.  The header fields are a whole number of words, so
+// this is the same as hashing them concatenated.
+fn checksum(fields: &[u8], map: &[u8]) -> u64 {
+    fnv(fnv(FNV_OFFSET, fields), map)
+}
+
+fn fnv(mut hash: u64, bytes: &[u8]) -> u64 {
+    let mut words = bytes.chunks_exact(8);
+    for word in &mut words {
+        hash = (hash ^ u64::from_le_bytes(word.try_into().unwrap())).wrapping_mul(FNV_PRIME);
+    }
+    for &byte in words.remainder() {
+        hash = (hash ^ byte as u64).wrapping_mul(FNV_PRIME);
+    }
+
+    hash
+}
+
+fn invalid(msg: &str) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, msg)
+}
+
+#[cfg(test)]
+mod test {
+    use std::{env, fs, path::PathBuf, process};
+
+    use super::*;
+    use crate::WhileLoops;
+
+    fn temp_path(name: &str) -> PathBuf {
+        env::temp_dir().join(format!("prime-sieve-{}-{}.bin", process::id(), name))
+    }
+
+    fn round_trip<C: SieveStorage>() {
+        for &upper_limit in &[2, 3, 25, 1_000, 100_001] {
+            let sieve = Sieve::<C>::build_with::<WhileLoops>(upper_limit);
+
+            let mut buf = Vec::new();
+            sieve.write_to(&mut buf).unwrap();
+            assert_eq!(buf.len(), HEADER_LEN + mem::size_of_val(&*sieve.map));
+
+            let read = Sieve::<C>::read_from(&buf[..]).unwrap();
+            assert_eq!(read.upper_limit, upper_limit);
+            assert!(*read.map == *sieve.map, "{} {}", C::NAME, upper_limit);
+
+            let path = temp_path(C::NAME);
+            fs::write(&path, &buf).unwrap();
+            let mapped = unsafe { Sieve::<C>::map_file(&path) }.unwrap();
+            fs::remove_file(&path).unwrap();
+
+            assert!(matches!(mapped.map, Map::Mapped(_)));
+            assert_eq!(mapped.upper_limit, upper_limit);
+            assert!(
+                mapped.primes().eq(sieve.primes()),
+                "{} {}",
+                C::NAME,
+                upper_limit
+            );
+            if upper_limit > 2 {
+                assert_eq!(mapped.count_primes(), sieve.count_primes());
+            }
+        }
+    }
+
+    fn rejects<C: SieveStorage>(buf: &[u8], name: &str) {
+        let err = Sieve::<C>::read_from(buf).err().unwrap();
+        assert_eq!(err.kind(), io::ErrorKind::InvalidData, "{}", C::NAME);
+
+        let path = temp_path(name);
+        fs::write(&path, buf).unwrap();
+        let err = unsafe { Sieve::<C>::map_file(&path) }.err().unwrap();
+        fs::remove_file(&path).unwrap();
+        assert_eq!(err.kind(), io::ErrorKind::InvalidData, "{}", C::NAME);
+    }
+
+    #[test]
+    fn every_storage_round_trips() {
+        round_trip::<bool>();
+        round_trip::<u8>();
+        round_trip::<u32>();
+        round_trip::<u64>();
+        round_trip::<usize>();
+    }
+
+    #[test]
+    fn rejects_other_chunk_types() {
+        let mut buf = Vec::new();
+        Sieve::<u8>::build_with::<WhileLoops>(100_001)
+            .write_to(&mut buf)
+            .unwrap();
+
+        rejects::<bool>(&buf, "u8-as-bool");
+        rejects::<u32>(&buf, "u8-as-u32");
+        rejects::<u64>(&buf, "u8-as-u64");
+        rejects::<usize>(&buf, "u8-as-usize");
+    }
+
+    #[test]
+    fn checksum_covers_the_header() {
+        let mut buf = Vec::new();
+        Sieve::<u64>::build_with::<WhileLoops>(1_000)
+            .write_to(&mut buf)
+            .unwrap();
+
+        // Same map size, but claims to sieve one more number.
+        let mut other_limit = buf.clone();
+        other_limit[24..32].copy_from_slice(&1_001u64.to_ne_bytes());
+        rejects::<u64>(&other_limit, "other-limit");
+    }
+
+    #[test]
+    fn rejects_corrupted_files() {
+        let mut buf = Vec::new();
+        Sieve::<bool>::build_with::<WhileLoops>(1_000)
+            .write_to(&mut buf)
+            .unwrap();
+
+        let mut flipped = buf.clone();
+        flipped[HEADER_LEN + 10] ^= 1;
+        rejects::<bool>(&flipped, "flipped");
+
+        // Not a valid bool, even with a matching checksum.
+        let mut invalid = buf.clone();
+        invalid[HEADER_LEN + 10] = 2;
+        let sum = checksum(&invalid[..CHECKSUM_AT], &invalid[HEADER_LEN..]);
+        invalid[CHECKSUM_AT..HEADER_LEN].copy_from_slice(&sum.to_ne_bytes());
+        rejects::<bool>(&invalid, "invalid-bool");
+
+        let mut other_version = buf.clone();
+        other_version[8..12].copy_from_slice(&(VERSION + 1).to_ne_bytes());
+        rejects::<bool>(&other_version, "other-version");
+
+        // Would allocate (or map) a map of a completely different size.
+        let mut huge = buf.clone();
+        huge[24..32].copy_from_slice(&(usize::MAX as u64).to_ne_bytes());
+        rejects::<bool>(&huge, "huge");
+
+        // Claims a map far larger than memory, but only has a few bytes of it; this must fail
+        // on reaching the end of the data, without allocating the whole map first.
+        let mut short = buf[..HEADER_LEN + 16].to_vec();
+        short[24..32].copy_from_slice(&(1u64 << 60).to_ne_bytes());
+        rejects::<bool>(&short, "short");
+
+        let truncated = &buf[..buf.len() - 1];
+        assert!(Sieve::<bool>::read_from(truncated).is_err());
+        let path = temp_path("truncated");
+        fs::write(&path, truncated).unwrap();
+        assert!(unsafe { Sieve::<bool>::map_file(&path) }.is_err());
+        fs::remove_file(&path).unwrap();
+    }
+}
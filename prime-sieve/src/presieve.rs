@@ -0,0 +1,129 @@
+//! Pre-sieving of the smallest odd primes with a repeated pattern.
+//!
+//! In the odd-only map, the multiples of an odd prime `p` repeat every `p` entries, so the
+//! multiples of 3, 5, 7, 11 and 13 repeat every `15015` entries, and also every `15015` chunks
+//! (of any size).  Instead of clearing them one by one, a precomputed block of chunks is anded
+//! into the map with the best available SIMD [`Backend`], and only larger factors are left for the
+//! loop strategy.
+
+use std::mem;
+
+use crate::{
+    simd::Backend,
+    storage::{bytes_of, bytes_of_mut, SieveStorage},
+    DefaultLoops, LoopStrategy, Sieve,
+};
+
+const SMALL_PRIMES: [usize; 5] = [3, 5, 7, 11, 13];
+
+/// Length, in chunks, of the repeated pattern.
+const PERIOD: usize = 3 * 5 * 7 * 11 * 13;
+
+/// The first factor not covered by the pattern.
+const FIRST_FACTOR: usize = 17;
+
+impl Sieve {
+    /// Builds the same sieve as [`Sieve::build`], but pre-sieving the smallest primes.
+    #[cfg_attr(feature = "disass", inline(never))]
+    pub fn build_presieved(upper_limit: usize) -> Self {
+        Sieve::build_presieved_with::<DefaultLoops>(upper_limit)
+    }
+}
+
+impl<C: SieveStorage> Sieve<C> {
+    /// Like [`Sieve::build_with`], but pre-sieving the smallest primes.
+    #[cfg_attr(feature = "disass", inline(never))]
+    pub fn build_presieved_with<L: LoopStrategy>(upper_limit: usize) -> Self {
+        Sieve::build_presieved_on::<L>(upper_limit, Backend::detect())
+    }
+
+    fn build_presieved_on<L: LoopStrategy>(upper_limit: usize, backend: Backend) -> Self {
+        let mut sieve = Sieve::new(upper_limit);
+        presieve(&mut sieve.map, backend);
+        L::clear_non_primes_from(&mut sieve, FIRST_FACTOR);
+        sieve
+    }
+}
+
+fn presieve<C: SieveStorage>(map: &mut [C], backend: Backend) {
+    let head = pattern::<C>(true);
+    let tail = pattern::<C>(false);
+
+    for (i, block) in map.chunks_mut(PERIOD).enumerate() {
+        let pattern = if i == 0 { &head } else { &tail };
+        let len = block.len();
+
+        // SAFETY: anding valid chunks always results in valid chunks (for bool, 0 or 1)
+        backend.and_assign(unsafe { bytes_of_mut(block) }, bytes_of(&pattern[..len]));
+    }
+}
+
+// Returns PERIOD chunks with the odd multiples of the small primes cleared; for the head of the
+// map, the small primes themselves are kept.
+fn pattern<C: SieveStorage>(head: bool) -> Vec<C> {
+    let entries = PERIOD * C::ENTRIES;
+
+    let mut pattern = C::new_map(entries);
+    pattern.truncate(PERIOD);
+    debug_assert_eq!(mem::size_of_val(&pattern[..]), PERIOD * mem::size_of::<C>());
+
+    for &p in &SMALL_PRIMES {
+        // Entry i stands for 2 * i + 1, so p is at p / 2 and 3 * p at p / 2 + p.
+        let first = if head { p / 2 + p } else { p / 2 };
+
+        for i in (first..entries).step_by(p) {
+            // SAFETY: i is less than entries, which is PERIOD * C::ENTRIES
+            unsafe { C::clear_unchecked(&mut pattern, i) };
+        }
+    }
+
+    pattern
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::{ForEach, ForLoops, WhileLoops};
+
+    // Large enough for several periods, even with one entry per bit of a u64.
+    const UPPER_LIMITS: [usize; 7] = [2, 3, 25, 1_000, 30_031, 100_001, 2 * 3 * PERIOD * 64 + 1];
+
+    fn check<C: SieveStorage, L: LoopStrategy>(backend: Backend) {
+        for &upper_limit in &UPPER_LIMITS {
+            let expected = Sieve::<C>::build_with::<L>(upper_limit);
+            let sieve = Sieve::<C>::build_presieved_on::<L>(upper_limit, backend);
+
+            assert!(
+                *sieve.map == *expected.map,
+                "{} {} {:?} {}",
+                C::NAME,
+                L::NAME,
+                backend,
+                upper_limit
+            );
+        }
+    }
+
+    #[test]
+    fn every_loop_strategy_finishes_the_same_sieve() {
+        check::<u8, ForLoops>(Backend::Portable);
+        check::<u8, ForEach>(Backend::Portable);
+        check::<u8, WhileLoops>(Backend::Portable);
+    }
+
+    #[test]
+    fn every_backend_presieves_every_storage() {
+        for &backend in Backend::ALL.iter().filter(|b| b.is_available()) {
+            check::<bool, WhileLoops>(backend);
+            check::<u8, WhileLoops>(backend);
+            check::<u32, WhileLoops>(backend);
+            check::<u64, WhileLoops>(backend);
+            check::<usize, WhileLoops>(backend);
+        }
+    }
+
+    #[test]
+    fn counts_the_same_primes() {
+        assert_eq!(Sieve::build_presieved(1_000_000).count_primes(), 78_498);
+    }
+}
@@ -0,0 +1,275 @@
+//! Iteration over the primes of a [`Sieve`], and queries built on top of it.
+//!
+//! In every storage backend, the entry at index `i` stands for the odd number `2 * i + 1`, and
+//! only the entries for numbers less than `upper_limit` (indices less than `upper_limit / 2`) are
+//! meaningful.
+
+use std::ops::{Bound, RangeBounds};
+
+use crate::{storage::SieveStorage, DefaultStorage, Sieve};
+
+impl<C: SieveStorage> Sieve<C> {
+    /// Returns an iterator over all primes less than `upper_limit`, in increasing order.
+    pub fn primes(&self) -> Primes<'_, C> {
+        self.primes_in(..)
+    }
+
+    /// Returns an iterator over the primes in `range` (and less than `upper_limit`), in
+    /// increasing order.
+    pub fn primes_in<R: RangeBounds<usize>>(&self, range: R) -> Primes<'_, C> {
+        let start = match range.start_bound() {
+            Bound::Included(&x) => x,
+            Bound::Excluded(&x) => x.saturating_add(1),
+            Bound::Unbounded => 0,
+        };
+        let end = match range.end_bound() {
+            Bound::Included(&x) => x.saturating_add(1),
+            Bound::Excluded(&x) => x,
+            Bound::Unbounded => self.upper_limit,
+        }
+        .min(self.upper_limit);
+
+        // The smallest odd number not less than start is at index start / 2, and the odd numbers
+        // less than end are at the indices less than end / 2.
+        Primes {
+            two: start <= 2 && 2 < end,
+            indices: Indices {
+                map: &self.map,
+                start: start / 2,
+                end: end / 2,
+            },
+        }
+    }
+
+    /// Returns the number of primes less than or equal to `number`.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `number` is not less than `upper_limit`.
+    #[cfg_attr(feature = "disass", inline(never))]
+    pub fn prime_pi(&self, number: usize) -> usize {
+        assert!(number < self.upper_limit, "number is beyond the sieve");
+
+        self.primes_in(..=number).count()
+    }
+
+    /// Returns the `n`-th prime, counting from zero (so that `nth_prime(0)` is 2), or `None` if it
+    /// is not less than `upper_limit`.
+    #[cfg_attr(feature = "disass", inline(never))]
+    pub fn nth_prime(&self, n: usize) -> Option<usize> {
+        self.primes().nth(n)
+    }
+
+    /// Returns the smallest prime greater than `number`, or `None` if there are no such primes
+    /// less than `upper_limit`.
+    pub fn next_prime(&self, number: usize) -> Option<usize> {
+        self.primes_in((Bound::Excluded(number), Bound::Unbounded))
+            .next()
+    }
+
+    /// Returns the largest prime less than `number`, or `None` if there is none.
+    ///
+    /// Primes are only known if less than `upper_limit`, so this also returns `None` if `number`
+    /// is greater than `upper_limit`.
+    pub fn prev_prime(&self, number: usize) -> Option<usize> {
+        if number > self.upper_limit {
+            return None;
+        }
+
+        self.primes_in(..number).next_back()
+    }
+}
+
+/// Iterator over the primes of a [`Sieve`], returned by [`Sieve::primes`] and
+/// [`Sieve::primes_in`].
+pub struct Primes<'a, C: SieveStorage = DefaultStorage> {
+    // 2 is not in the (odd-only) map.
+    two: bool,
+    indices: Indices<'a, C>,
+}
+
+impl<C: SieveStorage> Iterator for Primes<'_, C> {
+    type Item = usize;
+
+    fn next(&mut self) -> Option<usize> {
+        if self.two {
+            self.two = false;
+            return Some(2);
+        }
+
+        self.indices.next().map(|i| 2 * i + 1)
+    }
+
+    fn nth(&mut self, mut n: usize) -> Option<usize> {
+        if self.two {
+            self.two = false;
+            if n == 0 {
+                return Some(2);
+            }
+            n -= 1;
+        }
+
+        self.indices.nth(n).map(|i| 2 * i + 1)
+    }
+
+    fn count(self) -> usize {
+        self.two as usize + self.indices.count()
+    }
+}
+
+impl<C: SieveStorage> DoubleEndedIterator for Primes<'_, C> {
+    fn next_back(&mut self) -> Option<usize> {
+        match self.indices.next_back() {
+            Some(i) => Some(2 * i + 1),
+            None if self.two => {
+                self.two = false;
+                Some(2)
+            }
+            None => None,
+        }
+    }
+}
+
+// Iterator over the indices of the entries still set in start..end.
+struct Indices<'a, C> {
+    map: &'a [C],
+    start: usize,
+    end: usize,
+}
+
+impl<C: SieveStorage> Iterator for Indices<'_, C> {
+    type Item = usize;
+
+    fn next(&mut self) -> Option<usize> {
+        self.nth(0)
+    }
+
+    fn nth(&mut self, n: usize) -> Option<usize> {
+        match C::nth_set(self.map, self.start, self.end, n) {
+            Some(i) => {
+                self.start = i + 1;
+                Some(i)
+            }
+            None => {
+                self.start = self.end;
+                None
+            }
+        }
+    }
+
+    fn count(self) -> usize {
+        C::count_set(self.map, self.start, self.end)
+    }
+}
+
+impl<C: SieveStorage> DoubleEndedIterator for Indices<'_, C> {
+    fn next_back(&mut self) -> Option<usize> {
+        match C::prev_set(self.map, self.start, self.end) {
+            Some(i) => {
+                self.end = i;
+                Some(i)
+            }
+            None => {
+                self.end = self.start;
+                None
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::WhileLoops;
+
+    fn build<C: SieveStorage>(upper_limit: usize) -> Sieve<C> {
+        Sieve::build_with::<WhileLoops>(upper_limit)
+    }
+
+    // The primes, found by testing every number.
+    fn expected<C: SieveStorage>(sieve: &Sieve<C>, range: std::ops::Range<usize>) -> Vec<usize> {
+        range.filter(|&n| sieve.is_prime(n)).collect()
+    }
+
+    fn iterates_over_all_primes<C: SieveStorage>() {
+        for &upper_limit in &[2, 3, 4, 10, 25, 64, 65, 100, 1_000, 12_345] {
+            let sieve = build::<C>(upper_limit);
+            let primes: Vec<_> = sieve.primes().collect();
+
+            assert_eq!(primes, expected(&sieve, 0..upper_limit), "{}", upper_limit);
+            if upper_limit > 2 {
+                assert_eq!(primes.len(), sieve.count_primes());
+            }
+
+            let mut reversed: Vec<_> = sieve.primes().rev().collect();
+            reversed.reverse();
+            assert_eq!(reversed, primes);
+        }
+
+        let primes: Vec<_> = build::<C>(25).primes().collect();
+        assert_eq!(primes, [2, 3, 5, 7, 11, 13, 17, 19, 23]);
+    }
+
+    fn iterates_over_primes_in_ranges<C: SieveStorage>() {
+        let upper_limit = 300;
+        let sieve = build::<C>(upper_limit);
+
+        for start in 0..upper_limit + 5 {
+            for end in start..upper_limit + 5 {
+                let primes: Vec<_> = sieve.primes_in(start..end).collect();
+                let end = end.min(upper_limit);
+                assert_eq!(primes, expected(&sieve, start..end), "{}..{}", start, end);
+            }
+        }
+
+        assert_eq!(sieve.primes_in(2..=3).collect::<Vec<_>>(), [2, 3]);
+        assert_eq!(sieve.primes_in(290..).collect::<Vec<_>>(), [293]);
+        assert_eq!(sieve.primes_in(..).count(), 62);
+    }
+
+    fn answers_number_theoretic_queries<C: SieveStorage>() {
+        let upper_limit = 10_000;
+        let sieve = build::<C>(upper_limit);
+        let primes = expected(&sieve, 0..upper_limit);
+
+        for (k, &p) in primes.iter().enumerate() {
+            assert_eq!(sieve.nth_prime(k), Some(p));
+            assert_eq!(sieve.prime_pi(p), k + 1);
+            assert_eq!(sieve.prime_pi(p - 1), k);
+        }
+        assert_eq!(sieve.nth_prime(primes.len()), None);
+        assert_eq!(sieve.prime_pi(0), 0);
+        assert_eq!(sieve.prime_pi(upper_limit - 1), 1_229);
+
+        for number in 0..upper_limit {
+            let next = primes.iter().copied().find(|&p| p > number);
+            let prev = primes.iter().copied().rev().find(|&p| p < number);
+            assert_eq!(sieve.next_prime(number), next, "{}", number);
+            assert_eq!(sieve.prev_prime(number), prev, "{}", number);
+        }
+        assert_eq!(sieve.next_prime(usize::MAX), None);
+        assert_eq!(sieve.prev_prime(upper_limit), Some(9_973));
+        assert_eq!(sieve.prev_prime(usize::MAX), None);
+    }
+
+    #[test]
+    fn work_with_every_storage() {
+        iterates_over_all_primes::<bool>();
+        iterates_over_all_primes::<u8>();
+        iterates_over_all_primes::<u64>();
+
+        iterates_over_primes_in_ranges::<bool>();
+        iterates_over_primes_in_ranges::<u8>();
+        iterates_over_primes_in_ranges::<u64>();
+
+        answers_number_theoretic_queries::<bool>();
+        answers_number_theoretic_queries::<u8>();
+        answers_number_theoretic_queries::<u64>();
+    }
+
+    #[test]
+    #[should_panic]
+    fn prime_pi_panics_beyond_the_sieve() {
+        Sieve::build(100).prime_pi(100);
+    }
+}
@@ -0,0 +1,224 @@
+//! Segmented sieve, for upper limits whose full map would not fit in memory.
+//!
+//! The base primes up to `sqrt(upper_limit)` are found with a regular [`Sieve`], and then the
+//! range is sieved one cache-sized segment at a time.  Each segment is itself an (odd-only)
+//! `Sieve` map, where index `i` stands for the number `low + 2 * i + 1`, so memory usage is
+//! bounded by the size of a segment plus the base primes.
+
+use std::mem;
+
+use crate::{DefaultStorage, Sieve, SieveStorage};
+
+/// Size (in bytes of map) of an L1-sized segment.
+pub const L1_SEGMENT_BYTES: usize = 32 << 10;
+
+/// Size (in bytes of map) of an L2-sized segment; this is the default, since the number of
+/// segments, and with it the per-segment overhead of visiting every base prime, drops
+/// significantly for large limits.
+pub const L2_SEGMENT_BYTES: usize = 256 << 10;
+
+// How many (odd and even) numbers are covered by each byte of map.
+const NUMBERS_PER_BYTE: usize = 2 * DefaultStorage::ENTRIES / mem::size_of::<DefaultStorage>();
+
+pub struct SegmentedSieve {
+    upper_limit: usize,
+    segment_span: usize,
+}
+
+impl SegmentedSieve {
+    /// Creates a segmented sieve for the primes less than `upper_limit`, with L2-sized segments.
+    pub fn new(upper_limit: usize) -> Self {
+        SegmentedSieve::with_segment_bytes(upper_limit, L2_SEGMENT_BYTES)
+    }
+
+    /// Creates a segmented sieve for the primes less than `upper_limit`, with segments using
+    /// approximately `segment_bytes` of memory each.
+    pub fn with_segment_bytes(upper_limit: usize, segment_bytes: usize) -> Self {
+        assert!(segment_bytes > 0, "segments must not be empty");
+
+        SegmentedSieve {
+            upper_limit,
+            // Keeps the lower bound of every segment even, which the odd-only maps rely on.
+            segment_span: segment_bytes * NUMBERS_PER_BYTE,
+        }
+    }
+
+    #[cfg_attr(feature = "disass", inline(never))]
+    pub fn count_primes(&self) -> usize {
+        let mut segments = Segments::new(self.upper_limit, self.segment_span);
+        let mut count = if self.upper_limit > 2 { 1 } else { 0 };
+
+        while segments.sieve_next() {
+            // Sieve::count_primes() also counts 2, which is not in the segment.
+            count += segments.segment.count_primes() - 1;
+        }
+
+        count
+    }
+
+    /// Returns an iterator over the primes less than `upper_limit`, in increasing order.
+    pub fn primes(&self) -> Primes {
+        Primes {
+            segments: Segments::new(self.upper_limit, self.segment_span),
+            number: 1,
+            two: self.upper_limit > 2,
+        }
+    }
+}
+
+/// Iterator over the primes of a [`SegmentedSieve`].
+pub struct Primes {
+    segments: Segments,
+
+    // The last number that was checked; always odd.
+    number: usize,
+
+    two: bool,
+}
+
+impl Iterator for Primes {
+    type Item = usize;
+
+    fn next(&mut self) -> Option<usize> {
+        if self.two {
+            self.two = false;
+            return Some(2);
+        }
+
+        loop {
+            self.number += 2;
+
+            while self.number >= self.segments.high {
+                if !self.segments.sieve_next() {
+                    return None;
+                }
+            }
+
+            // SAFETY: number is odd and in low..high, and low is even, so the offset is odd and
+            // less than the upper_limit of the segment (high - low)
+            if unsafe {
+                self.segments
+                    .segment
+                    .is_prime_unchecked(self.number - self.segments.low)
+            } {
+                return Some(self.number);
+            }
+        }
+    }
+}
+
+struct Segments {
+    upper_limit: usize,
+    span: usize,
+
+    // Odd base primes, and the next odd multiple of each that remains to be cleared.
+    primes: Vec<usize>,
+    next: Vec<usize>,
+
+    // The current segment, covering low..high.
+    low: usize,
+    high: usize,
+    segment: Sieve,
+}
+
+impl Segments {
+    fn new(upper_limit: usize, span: usize) -> Self {
+        let enough = (upper_limit as f64).sqrt() as usize;
+        let base = Sieve::build(enough.max(2) + 1);
+        let primes: Vec<usize> = (3..=enough)
+            .step_by(2)
+            .filter(|&n| base.is_prime(n))
+            .collect();
+        let next = primes.iter().map(|&p| p * p).collect();
+
+        Segments {
+            upper_limit,
+            span,
+            primes,
+            next,
+            low: 0,
+            high: 0,
+            segment: Sieve::new(0),
+        }
+    }
+
+    /// Sieves the segment after the current one, returning `false` if there are none left.
+    #[cfg_attr(feature = "disass", inline(never))]
+    fn sieve_next(&mut self) -> bool {
+        if self.high >= self.upper_limit {
+            return false;
+        }
+
+        self.low = self.high;
+        self.high = self.upper_limit.min(self.low + self.span);
+        self.segment = Sieve::new(self.high - self.low);
+
+        if self.low == 0 && self.high > 1 {
+            // SAFETY: 1 is explicitly allowed, as long as the segment is not empty
+            unsafe { self.segment.clear_prime_unchecked(1) };
+        }
+
+        for (&prime, next) in self.primes.iter().zip(self.next.iter_mut()) {
+            // Base primes are sorted, so no later prime has multiples to clear either.
+            if prime * prime >= self.high {
+                break;
+            }
+
+            let mut mult = *next;
+            while mult < self.high {
+                // SAFETY: mult is odd (prime * 3, 5, 7, ...) and in low..high, and low is even,
+                // so the offset is odd and less than the upper_limit of the segment
+                unsafe { self.segment.clear_prime_unchecked(mult - self.low) };
+                mult += prime * 2;
+            }
+            *next = mult;
+        }
+
+        true
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn counts_match_the_regular_sieve() {
+        for &upper_limit in &[0, 1, 2, 3, 4, 10, 25, 100, 1_000, 12_345, 100_000] {
+            let expected = if upper_limit > 2 {
+                Sieve::build(upper_limit).count_primes()
+            } else {
+                0
+            };
+
+            // Tiny segments, so that primes and their multiples straddle many boundaries.
+            for &segment_bytes in &[1, 3, 64, L1_SEGMENT_BYTES] {
+                let sieve = SegmentedSieve::with_segment_bytes(upper_limit, segment_bytes);
+                assert_eq!(sieve.count_primes(), expected, "{}", upper_limit);
+            }
+        }
+    }
+
+    #[test]
+    fn primes_match_the_regular_sieve() {
+        let upper_limit = 50_001;
+        let regular = Sieve::build(upper_limit);
+        let expected: Vec<_> = (0..upper_limit).filter(|&n| regular.is_prime(n)).collect();
+
+        for &segment_bytes in &[1, 7, L1_SEGMENT_BYTES] {
+            let sieve = SegmentedSieve::with_segment_bytes(upper_limit, segment_bytes);
+            assert_eq!(sieve.primes().collect::<Vec<_>>(), expected);
+        }
+    }
+
+    #[test]
+    fn finds_all_primes_bellow_25() {
+        let primes: Vec<_> = SegmentedSieve::new(25).primes().collect();
+        assert_eq!(primes, [2, 3, 5, 7, 11, 13, 17, 19, 23]);
+    }
+
+    #[test]
+    fn counts_primes_bellow_ten_million() {
+        assert_eq!(SegmentedSieve::new(10_000_000).count_primes(), 664_579);
+    }
+}
@@ -0,0 +1,267 @@
+//! SIMD kernels for counting and pre-sieving, with runtime CPU dispatch.
+//!
+//! The kernels work on the raw bytes of a map, so they are shared by every storage.  The best
+//! available [`Backend`] is selected at runtime with `is_x86_feature_detected!`, and the portable
+//! one is always available as a fallback.
+
+use std::convert::TryInto;
+
+/// A set of kernels, each requiring some CPU features.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Backend {
+    /// Plain Rust, for any CPU.
+    Portable,
+
+    /// SSE2 and the POPCNT instruction (which came with SSE4.2).
+    Sse42,
+
+    /// AVX2, counting with a nibble lookup table instead of POPCNT.
+    Avx2,
+}
+
+impl Backend {
+    /// All backends, from the least to the most capable.
+    pub const ALL: [Backend; 3] = [Backend::Portable, Backend::Sse42, Backend::Avx2];
+
+    /// Returns the most capable backend available on this CPU.
+    pub fn detect() -> Self {
+        Backend::ALL
+            .iter()
+            .rev()
+            .copied()
+            .find(|backend| backend.is_available())
+            .unwrap()
+    }
+
+    /// Returns whether the CPU supports the features required by this backend.
+    pub fn is_available(self) -> bool {
+        match self {
+            Backend::Portable => true,
+            #[cfg(target_arch = "x86_64")]
+            Backend::Sse42 => {
+                is_x86_feature_detected!("sse4.2") && is_x86_feature_detected!("popcnt")
+            }
+            #[cfg(target_arch = "x86_64")]
+            Backend::Avx2 => is_x86_feature_detected!("avx2"),
+            #[allow(unreachable_patterns)]
+            _ => false,
+        }
+    }
+
+    /// Short name of the backend, for reporting.
+    pub fn name(self) -> &'static str {
+        match self {
+            Backend::Portable => "portable",
+            Backend::Sse42 => "sse4.2",
+            Backend::Avx2 => "avx2",
+        }
+    }
+
+    /// Counts the bits set in `bytes`.
+    #[cfg_attr(feature = "disass", inline(never))]
+    pub(crate) fn count_ones(self, bytes: &[u8]) -> usize {
+        assert!(self.is_available(), "{} is not available", self.name());
+
+        match self {
+            // SAFETY: just checked that the CPU supports the required features
+            #[cfg(target_arch = "x86_64")]
+            Backend::Sse42 => unsafe { x86::count_ones_popcnt(bytes) },
+            #[cfg(target_arch = "x86_64")]
+            Backend::Avx2 => unsafe { x86::count_ones_avx2(bytes) },
+            _ => portable::count_ones(bytes),
+        }
+    }
+
+    /// Bitwise ands `src` into `dst`, which must have the same length.
+    #[cfg_attr(feature = "disass", inline(never))]
+    pub(crate) fn and_assign(self, dst: &mut [u8], src: &[u8]) {
+        assert!(self.is_available(), "{} is not available", self.name());
+        assert_eq!(dst.len(), src.len());
+
+        match self {
+            // SAFETY: just checked that the CPU supports the required features, and that the
+            // slices have the same length
+            #[cfg(target_arch = "x86_64")]
+            Backend::Sse42 => unsafe { x86::and_assign_sse2(dst, src) },
+            #[cfg(target_arch = "x86_64")]
+            Backend::Avx2 => unsafe { x86::and_assign_avx2(dst, src) },
+            _ => portable::and_assign(dst, src),
+        }
+    }
+}
+
+mod portable {
+    use super::*;
+
+    #[inline]
+    pub fn count_ones(bytes: &[u8]) -> usize {
+        let mut words = bytes.chunks_exact(8);
+        let mut count = 0;
+
+        for word in &mut words {
+            count += u64::from_ne_bytes(word.try_into().unwrap()).count_ones() as usize;
+        }
+        for byte in words.remainder() {
+            count += byte.count_ones() as usize;
+        }
+
+        count
+    }
+
+    #[inline]
+    pub fn and_assign(dst: &mut [u8], src: &[u8]) {
+        let mut dst_words = dst.chunks_exact_mut(8);
+        let mut src_words = src.chunks_exact(8);
+
+        for (d, s) in (&mut dst_words).zip(&mut src_words) {
+            let word = u64::from_ne_bytes((&*d).try_into().unwrap())
+                & u64::from_ne_bytes(s.try_into().unwrap());
+            d.copy_from_slice(&word.to_ne_bytes());
+        }
+        for (d, s) in dst_words
+            .into_remainder()
+            .iter_mut()
+            .zip(src_words.remainder())
+        {
+            *d &= s;
+        }
+    }
+}
+
+#[cfg(target_arch = "x86_64")]
+mod x86 {
+    use std::arch::x86_64::*;
+
+    use super::portable;
+
+    /// The portable loop, but compiled to use the POPCNT instruction.
+    ///
+    /// # Safety
+    ///
+    /// POPCNT must be available.
+    #[target_feature(enable = "popcnt")]
+    pub unsafe fn count_ones_popcnt(bytes: &[u8]) -> usize {
+        portable::count_ones(bytes)
+    }
+
+    /// Counts the bits of each nibble with a lookup table (vpshufb), and sums the per-byte counts
+    /// into 64-bit lanes (vpsadbw).
+    ///
+    /// # Safety
+    ///
+    /// AVX2 must be available.
+    #[target_feature(enable = "avx2")]
+    pub unsafe fn count_ones_avx2(bytes: &[u8]) -> usize {
+        #[rustfmt::skip]
+        let lookup = _mm256_setr_epi8(
+            0, 1, 1, 2, 1, 2, 2, 3, 1, 2, 2, 3, 2, 3, 3, 4,
+            0, 1, 1, 2, 1, 2, 2, 3, 1, 2, 2, 3, 2, 3, 3, 4,
+        );
+        let low_mask = _mm256_set1_epi8(0x0f);
+        let zero = _mm256_setzero_si256();
+
+        let mut total = zero;
+        let mut vectors = bytes.chunks_exact(32);
+
+        for vector in &mut vectors {
+            let v = _mm256_loadu_si256(vector.as_ptr() as *const __m256i);
+            let lo = _mm256_and_si256(v, low_mask);
+            let hi = _mm256_and_si256(_mm256_srli_epi16(v, 4), low_mask);
+            let counts = _mm256_add_epi8(
+                _mm256_shuffle_epi8(lookup, lo),
+                _mm256_shuffle_epi8(lookup, hi),
+            );
+            total = _mm256_add_epi64(total, _mm256_sad_epu8(counts, zero));
+        }
+
+        let mut lanes = [0u64; 4];
+        _mm256_storeu_si256(lanes.as_mut_ptr() as *mut __m256i, total);
+
+        lanes.iter().sum::<u64>() as usize + portable::count_ones(vectors.remainder())
+    }
+
+    /// # Safety
+    ///
+    /// The slices must have the same length (and SSE2 must be available, as is always the case on
+    /// x86-64).
+    #[target_feature(enable = "sse2")]
+    pub unsafe fn and_assign_sse2(dst: &mut [u8], src: &[u8]) {
+        let len = dst.len() / 16 * 16;
+
+        for i in (0..len).step_by(16) {
+            let d = dst.as_mut_ptr().add(i) as *mut __m128i;
+            let s = src.as_ptr().add(i) as *const __m128i;
+            _mm_storeu_si128(d, _mm_and_si128(_mm_loadu_si128(d), _mm_loadu_si128(s)));
+        }
+
+        portable::and_assign(&mut dst[len..], &src[len..]);
+    }
+
+    /// # Safety
+    ///
+    /// The slices must have the same length, and AVX2 must be available.
+    #[target_feature(enable = "avx2")]
+    pub unsafe fn and_assign_avx2(dst: &mut [u8], src: &[u8]) {
+        let len = dst.len() / 32 * 32;
+
+        for i in (0..len).step_by(32) {
+            let d = dst.as_mut_ptr().add(i) as *mut __m256i;
+            let s = src.as_ptr().add(i) as *const __m256i;
+            _mm256_storeu_si256(
+                d,
+                _mm256_and_si256(_mm256_loadu_si256(d), _mm256_loadu_si256(s)),
+            );
+        }
+
+        portable::and_assign(&mut dst[len..], &src[len..]);
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    // Deterministic, but irregular enough to exercise every lane.
+    fn noise(len: usize, seed: u64) -> Vec<u8> {
+        let mut state = seed;
+        (0..len)
+            .map(|_| {
+                state ^= state << 13;
+                state ^= state >> 7;
+                state ^= state << 17;
+                state as u8
+            })
+            .collect()
+    }
+
+    #[test]
+    fn every_backend_agrees_with_the_portable_one() {
+        let a = noise(300, 0x9e37_79b9_7f4a_7c15);
+        let b = noise(300, 0x2545_f491_4f6c_dd1d);
+
+        for backend in Backend::ALL.iter().filter(|b| b.is_available()) {
+            for start in 0..8 {
+                for end in start..a.len() {
+                    let bytes = &a[start..end];
+                    let expected: usize = bytes.iter().map(|b| b.count_ones() as usize).sum();
+                    assert_eq!(backend.count_ones(bytes), expected, "{:?}", backend);
+
+                    let mut dst = a[start..end].to_vec();
+                    backend.and_assign(&mut dst, &b[start..end]);
+                    let expected: Vec<_> = bytes
+                        .iter()
+                        .zip(&b[start..end])
+                        .map(|(x, y)| x & y)
+                        .collect();
+                    assert_eq!(dst, expected, "{:?}", backend);
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn detects_an_available_backend() {
+        assert!(Backend::detect().is_available());
+        assert!(Backend::Portable.is_available());
+    }
+}
@@ -0,0 +1,308 @@
+//! Storage strategies for the (odd-only) map of a [`Sieve`](crate::Sieve).
+//!
+//! The map is a `Vec` of chunks, where each chunk stores one or more entries, and the entry at
+//! index `i` stands for the odd number `2 * i + 1`.  All functions take the map as a slice, so
+//! that they can also be used on disjoint parts of it (e.g. by parallel workers).
+
+/// A chunk of the map of a [`Sieve`](crate::Sieve).
+///
+/// Implemented for `bool` (one entry per chunk) and for the unsigned integers `u8`, `u32`, `u64`
+/// and `usize` (one entry per bit).
+///
+/// # Safety
+///
+/// Maps are written to and read from files as raw bytes, so implementors must be plain data
+/// without padding, and [`SieveStorage::check_bytes`] must reject any bytes that do not hold valid
+/// chunks.
+pub unsafe trait SieveStorage: Copy + PartialEq + Send + Sync + 'static {
+    /// Short name of the strategy, for reporting.
+    const NAME: &'static str;
+
+    /// Number of entries stored in each chunk.
+    const ENTRIES: usize;
+
+    /// Creates a map with the first `entries` entries set, and all others (if any) cleared.
+    fn new_map(entries: usize) -> Vec<Self>;
+
+    /// # Safety
+    ///
+    /// The `index` argument must be less than `map.len() * Self::ENTRIES`.
+    unsafe fn clear_unchecked(map: &mut [Self], index: usize);
+
+    /// # Safety
+    ///
+    /// The `index` argument must be less than `map.len() * Self::ENTRIES`.
+    unsafe fn is_set_unchecked(map: &[Self], index: usize) -> bool;
+
+    /// Counts the entries set in `start..end`, which must be within the map.
+    fn count_set(map: &[Self], start: usize, end: usize) -> usize;
+
+    /// Returns the index of the first entry set in `start..end`, which must be within the map.
+    fn next_set(map: &[Self], start: usize, end: usize) -> Option<usize>;
+
+    /// Returns the index of the last entry set in `start..end`, which must be within the map.
+    fn prev_set(map: &[Self], start: usize, end: usize) -> Option<usize>;
+
+    /// Returns whether `bytes` (in native byte order) only hold valid chunks; the default accepts
+    /// anything, which is correct for the unsigned integers.
+    fn check_bytes(_bytes: &[u8]) -> bool {
+        true
+    }
+
+    /// Returns the index of the `n`-th (counting from zero) entry set in `start..end`, which must
+    /// be within the map.
+    fn nth_set(map: &[Self], mut start: usize, end: usize, mut n: usize) -> Option<usize> {
+        loop {
+            let i = Self::next_set(map, start, end)?;
+            if n == 0 {
+                return Some(i);
+            }
+            n -= 1;
+            start = i + 1;
+        }
+    }
+}
+
+unsafe impl SieveStorage for bool {
+    const NAME: &'static str = "bool";
+    const ENTRIES: usize = 1;
+
+    fn new_map(entries: usize) -> Vec<Self> {
+        vec![true; entries]
+    }
+
+    unsafe fn clear_unchecked(map: &mut [Self], index: usize) {
+        debug_assert!(index < map.len());
+
+        *map.get_unchecked_mut(index) = false;
+    }
+
+    unsafe fn is_set_unchecked(map: &[Self], index: usize) -> bool {
+        debug_assert!(index < map.len());
+
+        *map.get_unchecked(index)
+    }
+
+    fn count_set(map: &[Self], start: usize, end: usize) -> usize {
+        if start >= end {
+            return 0;
+        }
+
+        map[start..end].iter().filter(|&x| *x).count()
+    }
+
+    fn next_set(map: &[Self], start: usize, end: usize) -> Option<usize> {
+        if start >= end {
+            return None;
+        }
+
+        map[start..end].iter().position(|&x| x).map(|i| start + i)
+    }
+
+    fn prev_set(map: &[Self], start: usize, end: usize) -> Option<usize> {
+        if start >= end {
+            return None;
+        }
+
+        map[start..end].iter().rposition(|&x| x).map(|i| start + i)
+    }
+
+    fn check_bytes(bytes: &[u8]) -> bool {
+        bytes.iter().all(|&b| b <= 1)
+    }
+}
+
+// Instead of testing each bit, whole chunks are skipped when empty, and set bits are found with
+// trailing_zeros() and leading_zeros().
+macro_rules! impl_bit_storage {
+    ($($t:ty),*) => {$(
+        unsafe impl SieveStorage for $t {
+            const NAME: &'static str = stringify!($t);
+            const ENTRIES: usize = <$t>::BITS as usize;
+
+            fn new_map(entries: usize) -> Vec<Self> {
+                let mut map = vec![<$t>::MAX; entries / Self::ENTRIES + 1];
+
+                // Zero extra bits at the end, so counting can use count_ones.
+                let keep = entries % Self::ENTRIES;
+                let keep_mask: $t = (1 << keep) - 1;
+
+                let last = map.len() - 1;
+
+                // SAFETY: just computed last from map.len()
+                unsafe { *map.get_unchecked_mut(last) &= keep_mask };
+
+                map
+            }
+
+            unsafe fn clear_unchecked(map: &mut [Self], index: usize) {
+                let word = index / Self::ENTRIES;
+                let bit = index % Self::ENTRIES;
+
+                debug_assert!(word < map.len());
+                *map.get_unchecked_mut(word) &= !(1 << bit)
+            }
+
+            unsafe fn is_set_unchecked(map: &[Self], index: usize) -> bool {
+                let word = index / Self::ENTRIES;
+                let bit = index % Self::ENTRIES;
+
+                debug_assert!(word < map.len());
+                map.get_unchecked(word) & (1 << bit) != 0
+            }
+
+            fn count_set(map: &[Self], mut start: usize, end: usize) -> usize {
+                let mut count = 0;
+
+                while start < end {
+                    let (bits, chunk_end) = Self::front_chunk(map, start, end);
+                    count += bits.count_ones() as usize;
+                    start = chunk_end;
+                }
+
+                count
+            }
+
+            fn next_set(map: &[Self], mut start: usize, end: usize) -> Option<usize> {
+                while start < end {
+                    let (bits, chunk_end) = Self::front_chunk(map, start, end);
+
+                    if bits != 0 {
+                        return Some(start + bits.trailing_zeros() as usize);
+                    }
+
+                    start = chunk_end;
+                }
+
+                None
+            }
+
+            fn prev_set(map: &[Self], start: usize, mut end: usize) -> Option<usize> {
+                while start < end {
+                    let last = end - 1;
+                    let word = last / Self::ENTRIES;
+
+                    // Move the bit for last to the top, dropping any bits past it.
+                    let bits = map[word] << (Self::ENTRIES - 1 - last % Self::ENTRIES);
+
+                    if bits != 0 {
+                        let i = last - bits.leading_zeros() as usize;
+                        return if i >= start { Some(i) } else { None };
+                    }
+
+                    end = word * Self::ENTRIES;
+                }
+
+                None
+            }
+
+            // Skips whole chunks by counting their bits, which is much faster than finding each
+            // set bit.
+            fn nth_set(map: &[Self], mut start: usize, end: usize, mut n: usize) -> Option<usize> {
+                while start < end {
+                    let (mut bits, chunk_end) = Self::front_chunk(map, start, end);
+                    let ones = bits.count_ones() as usize;
+
+                    if n < ones {
+                        for _ in 0..n {
+                            bits &= bits - 1;
+                        }
+
+                        return Some(start + bits.trailing_zeros() as usize);
+                    }
+
+                    n -= ones;
+                    start = chunk_end;
+                }
+
+                None
+            }
+        }
+
+        impl BitChunk for $t {
+            fn front_chunk(map: &[Self], start: usize, end: usize) -> (Self, usize) {
+                let word = start / Self::ENTRIES;
+                let chunk_end = ((word + 1) * Self::ENTRIES).min(end);
+                let width = chunk_end - start;
+
+                let mut bits = map[word] >> (start % Self::ENTRIES);
+                if width < Self::ENTRIES {
+                    bits &= (1 << width) - 1;
+                }
+
+                (bits, chunk_end)
+            }
+        }
+    )*};
+}
+
+trait BitChunk: Sized {
+    // Returns the bits of the chunk that contains start, shifted so that start is bit zero and
+    // without any bits at or beyond end, as well as the index just past that chunk (or end).
+    fn front_chunk(map: &[Self], start: usize, end: usize) -> (Self, usize);
+}
+
+impl_bit_storage!(u8, u32, u64, usize);
+
+/// Returns the raw bytes of a map.
+pub(crate) fn bytes_of<C: SieveStorage>(map: &[C]) -> &[u8] {
+    // SAFETY: SieveStorage implementors are plain data without padding
+    unsafe { std::slice::from_raw_parts(map.as_ptr() as *const u8, std::mem::size_of_val(map)) }
+}
+
+/// Returns the raw bytes of a map, for writing.
+///
+/// # Safety
+///
+/// The chunks must not be used until the bytes have been checked with `C::check_bytes`, unless
+/// only bytes of valid chunks were written.
+pub(crate) unsafe fn bytes_of_mut<C: SieveStorage>(map: &mut [C]) -> &mut [u8] {
+    std::slice::from_raw_parts_mut(map.as_mut_ptr() as *mut u8, std::mem::size_of_val(map))
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    // Checks every query against a plain Vec<bool> with the same entries.
+    fn check<C: SieveStorage>() {
+        let entries = 3 * C::ENTRIES + 5;
+        let mut map = C::new_map(entries);
+        let mut expected = vec![true; entries];
+
+        for i in (0..entries).filter(|i| i % 3 == 0 || i % 7 == 1) {
+            unsafe { C::clear_unchecked(&mut map, i) };
+            expected[i] = false;
+        }
+
+        for (i, &set) in expected.iter().enumerate() {
+            assert_eq!(unsafe { C::is_set_unchecked(&map, i) }, set);
+        }
+
+        for start in 0..=entries {
+            for end in start..=entries {
+                let set: Vec<_> = (start..end).filter(|&i| expected[i]).collect();
+
+                assert_eq!(C::count_set(&map, start, end), set.len());
+                assert_eq!(C::next_set(&map, start, end), set.first().copied());
+                assert_eq!(C::prev_set(&map, start, end), set.last().copied());
+                for n in 0..=set.len() {
+                    assert_eq!(C::nth_set(&map, start, end, n), set.get(n).copied());
+                }
+            }
+        }
+
+        // Entries past the end are never set.
+        let len = map.len() * C::ENTRIES;
+        assert_eq!(C::count_set(&map, 0, len), C::count_set(&map, 0, entries));
+    }
+
+    #[test]
+    fn all_storages_agree_with_a_plain_map() {
+        check::<bool>();
+        check::<u8>();
+        check::<u32>();
+        check::<u64>();
+        check::<usize>();
+    }
+}
@@ -0,0 +1,198 @@
+//! Sieve with mod 30 wheel factorization.
+//!
+//! Only the numbers coprime to 30 (i.e. not multiples of 2, 3 or 5) can be primes, apart from 2,
+//! 3 and 5 themselves, and there are exactly 8 of them in every 30 consecutive integers.  So the
+//! map stores one byte for each 30 integers, where bit `i` of byte `k` stands for the number
+//! `30 * k + RESIDUES[i]`; this takes 8/15 of the memory of the odd-only bit-based map.
+
+use crate::{miller_rabin::is_prime_u64, simd::Backend};
+
+/// The residues mod 30 of the numbers that are coprime to 30.
+const RESIDUES: [usize; 8] = [1, 7, 11, 13, 17, 19, 23, 29];
+
+/// Distance from each residue to the next one (wrapping around to 31).
+const GAPS: [usize; 8] = [6, 4, 2, 4, 2, 4, 6, 2];
+
+const NONE: u8 = u8::MAX;
+
+/// The bit for each residue mod 30, or NONE if numbers with that residue are not coprime to 30.
+const BITS: [u8; 30] = {
+    let mut bits = [NONE; 30];
+    let mut i = 0;
+    while i < RESIDUES.len() {
+        bits[RESIDUES[i]] = i as u8;
+        i += 1;
+    }
+    bits
+};
+
+pub struct WheelSieve {
+    upper_limit: usize,
+    map: Vec<u8>,
+}
+
+impl WheelSieve {
+    #[cfg_attr(feature = "disass", inline(never))]
+    pub fn build(upper_limit: usize) -> Self {
+        let mut sieve = WheelSieve::new(upper_limit);
+        sieve.clear_non_primes();
+        sieve
+    }
+
+    /// Returns whether `number` is prime, falling back to [`is_prime_u64`] when it is not less
+    /// than `upper_limit`.
+    #[cfg_attr(feature = "disass", inline(never))]
+    pub fn is_prime(&self, number: usize) -> bool {
+        if number >= self.upper_limit {
+            is_prime_u64(number as u64)
+        } else if BITS[number % 30] != NONE {
+            // SAFETY: just checked that number is coprime to 30 and less than upper_limit
+            unsafe { self.is_prime_unchecked(number) }
+        } else {
+            matches!(number, 2 | 3 | 5)
+        }
+    }
+
+    #[cfg_attr(feature = "disass", inline(never))]
+    pub fn not_prime(&self, number: usize) -> bool {
+        !self.is_prime(number)
+    }
+
+    #[cfg_attr(feature = "disass", inline(never))]
+    pub fn count_primes(&self) -> usize {
+        // Assumes that extra bits at the end are zeroed/cleared.
+        let wheel = Backend::detect().count_ones(&self.map);
+        let small = [2, 3, 5].iter().filter(|&&p| p < self.upper_limit).count();
+        wheel + small
+    }
+
+    #[cfg_attr(feature = "disass", inline(never))]
+    fn new(upper_limit: usize) -> Self {
+        let mut map = vec![u8::MAX; upper_limit / 30 + 1];
+
+        // Zero the bits for numbers at or beyond upper_limit at the end, so count_primes can use
+        // count_ones.
+        let last = map.len() - 1;
+        let rem = upper_limit % 30;
+        let keep = RESIDUES.iter().filter(|&&r| r < rem).count();
+        let keep_mask = ((1u16 << keep) - 1) as u8;
+
+        // SAFETY: just computed last from map.len()
+        unsafe { *map.get_unchecked_mut(last) &= keep_mask };
+
+        WheelSieve { upper_limit, map }
+    }
+
+    #[cfg_attr(feature = "disass", inline(never))]
+    fn clear_non_primes(&mut self) {
+        if self.upper_limit > 1 {
+            // SAFETY: 1 is explicitly allowed
+            unsafe { self.clear_prime_unchecked(1) };
+        }
+
+        let enough = (self.upper_limit as f64).sqrt() as usize;
+
+        // Go over the candidates, starting at 7, by walking the wheel.
+        let mut factor = 7;
+        let mut factor_res = 1;
+        while factor <= enough {
+            // SAFETY: factor is coprime to 30 (we are walking the wheel) and less than
+            // upper_limit (enough < upper_limit)
+            if unsafe { self.is_prime_unchecked(factor) } {
+                // Only multiples of factor by numbers that are themselves coprime to 30 need to
+                // be cleared, so also walk the wheel for the other factor, starting at factor.
+                let mut mult = factor * factor;
+                let mut other_res = factor_res;
+
+                while mult < self.upper_limit {
+                    // SAFETY: mult is the product of two numbers coprime to 30, and therefore
+                    // also coprime to 30, and less than upper_limit
+                    unsafe { self.clear_prime_unchecked(mult) };
+                    mult += factor * GAPS[other_res];
+                    other_res = (other_res + 1) % 8;
+                }
+            }
+
+            factor += GAPS[factor_res];
+            factor_res = (factor_res + 1) % 8;
+        }
+    }
+
+    /// # Safety
+    ///
+    /// The `number` argument must be coprime to 30, and one or less than `self.upper_limit`.
+    #[cfg_attr(feature = "disass", inline(never))]
+    unsafe fn clear_prime_unchecked(&mut self, number: usize) {
+        debug_assert!(BITS[number % 30] != NONE);
+        debug_assert!(number < self.upper_limit || number == 1);
+
+        let byte = number / 30;
+        let bit = *BITS.get_unchecked(number % 30);
+
+        debug_assert!(byte < self.map.len());
+        *self.map.get_unchecked_mut(byte) &= !(1 << bit)
+    }
+
+    /// # Safety
+    ///
+    /// The `number` argument must be coprime to 30 and less than `self.upper_limit`.
+    #[cfg_attr(feature = "disass", inline(never))]
+    unsafe fn is_prime_unchecked(&self, number: usize) -> bool {
+        debug_assert!(BITS[number % 30] != NONE);
+        debug_assert!(number < self.upper_limit);
+
+        let byte = number / 30;
+        let bit = *BITS.get_unchecked(number % 30);
+
+        debug_assert!(byte < self.map.len());
+        self.map.get_unchecked(byte) & (1 << bit) != 0
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::Sieve;
+
+    #[test]
+    fn finds_all_primes_bellow_25() {
+        let sieve = WheelSieve::build(25);
+
+        let primes: Vec<_> = (0..25).filter(|&n| sieve.is_prime(n)).collect();
+        assert_eq!(primes, [2, 3, 5, 7, 11, 13, 17, 19, 23]);
+
+        assert_eq!(sieve.count_primes(), 9);
+    }
+
+    #[test]
+    fn agrees_with_the_odd_only_sieve() {
+        for upper_limit in (0..200).chain([1_000, 12_345, 1_000_001].iter().copied()) {
+            let wheel = WheelSieve::build(upper_limit);
+            let sieve = Sieve::build(upper_limit.max(3));
+
+            for n in 0..upper_limit + 10 {
+                assert_eq!(
+                    wheel.is_prime(n),
+                    sieve.is_prime(n),
+                    "{} {}",
+                    upper_limit,
+                    n
+                );
+            }
+
+            if upper_limit > 2 {
+                assert_eq!(
+                    wheel.count_primes(),
+                    sieve.count_primes(),
+                    "{}",
+                    upper_limit
+                );
+            }
+        }
+    }
+
+    #[test]
+    fn uses_one_byte_per_30_integers() {
+        assert_eq!(WheelSieve::new(3_000_000).map.len(), 100_001);
+    }
+}